@@ -0,0 +1,443 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{
+    asset::{AssetLoader, HandleId, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use iyes_loopless::prelude::*;
+use serde::Deserialize;
+
+use crate::statemanagement::PauseState;
+
+/// Plays sprite sheet animations from clips made from Aseprite tags, with a
+/// `.overlay.ron` file for what the tags can't hold
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<AnimationClips>()
+            .add_asset::<ClipOverlay>()
+            .init_asset_loader::<ClipOverlayLoader>()
+            .add_event::<FrameEvent>()
+            .add_system(animate_sprites.run_not_in_state(PauseState::Paused));
+    }
+}
+
+/// The clips for one sprite sheet
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "4e8d2b6f-1c3a-4f5e-9d7b-8a2c6e0f1b3d"]
+pub struct AnimationClips {
+    pub clips: Vec<AnimationClip>,
+}
+
+impl AnimationClips {
+    pub fn get(&self, name: &str) -> Option<&AnimationClip> {
+        self.clips.iter().find(|clip| clip.name == name)
+    }
+}
+
+/// A run of frames from a sprite sheet, played in order
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Sheet index of the first frame
+    pub first: usize,
+    /// Sheet index of the last frame
+    pub last: usize,
+    /// Seconds each frame is shown for
+    pub frame_duration: f32,
+    /// Seconds for each frame in turn, where they differ. Frames past the
+    /// end of the list use `frame_duration`.
+    pub frame_durations: Vec<f32>,
+    pub mode: LoopMode,
+    /// Named moments in the clip, announced as their frame comes up
+    pub events: Vec<ClipEvent>,
+}
+
+impl AnimationClip {
+    fn len(&self) -> usize {
+        self.last.saturating_sub(self.first) + 1
+    }
+
+    /// What stops the clip, with any events laid over it, playing on a sheet
+    /// with this many frames, if anything does
+    fn problem(
+        &self,
+        frame_count: usize,
+        overlay_events: &[ClipEvent],
+    ) -> Option<String> {
+        if self.first > self.last {
+            return Some(format!(
+                "its last frame {} comes before its first {}",
+                self.last, self.first
+            ));
+        }
+        if self.last >= frame_count {
+            return Some(format!(
+                "it runs to frame {}, but the sheet has {} frames",
+                self.last, frame_count
+            ));
+        }
+        self.events
+            .iter()
+            .chain(overlay_events)
+            .find(|event| !(self.first..=self.last).contains(&event.frame))
+            .map(|event| {
+                format!(
+                    "its {} event is on frame {}, outside the clip",
+                    event.name, event.frame
+                )
+            })
+    }
+
+    /// Seconds the frame this far into the clip is shown for
+    fn duration(&self, frame: usize) -> f32 {
+        self.frame_durations
+            .get(frame)
+            .copied()
+            .unwrap_or(self.frame_duration)
+            .max(0.001)
+    }
+
+    /// The events, the clip's own and those laid over it, raised when the
+    /// frame this far into the clip comes up
+    fn events_on<'a>(
+        &'a self,
+        frame: usize,
+        overlay_events: &'a [ClipEvent],
+    ) -> impl Iterator<Item = &'a ClipEvent> {
+        self.events
+            .iter()
+            .chain(overlay_events)
+            .filter(move |event| event.frame == self.first + frame)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum LoopMode {
+    /// Starts again from the first frame after the last
+    Loop,
+    /// Stays on the last frame
+    Once,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClipEvent {
+    /// Sheet index of the frame the event is raised on
+    pub frame: usize,
+    pub name: String,
+}
+
+/// Laid over the clips made from an Aseprite file, to say what the tags
+/// can't: which clip a state without a tag of its own plays, and the frame
+/// events to raise in each clip
+#[derive(Debug, Clone, Default, Deserialize, TypeUuid)]
+#[uuid = "7d2f9a4c-3e1b-4b6d-8f5a-2c9e0b7d1a6f"]
+#[serde(default)]
+pub struct ClipOverlay {
+    /// By state name, as given by `AnimationState::clip_name`
+    pub states: HashMap<String, StateClip>,
+    /// By clip name
+    pub events: HashMap<String, Vec<ClipEvent>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StateClip {
+    /// The clip to play for the state
+    pub clip: String,
+    /// Replaces the clip's own mode, so a looping tag can also be played once
+    #[serde(default)]
+    pub mode: Option<LoopMode>,
+}
+
+/// Sent when a frame with a `ClipEvent` comes up
+#[derive(Debug, Clone)]
+pub struct FrameEvent {
+    pub entity: Entity,
+    pub name: String,
+}
+
+/// What a character is doing, each with a clip of the same name unless an
+/// overlay points it at another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationState {
+    Idle,
+    Walk,
+    Peck,
+    Jump,
+    Climb,
+    Fall,
+}
+
+impl AnimationState {
+    pub fn clip_name(self) -> &'static str {
+        match self {
+            AnimationState::Idle => "idle",
+            AnimationState::Walk => "walk",
+            AnimationState::Peck => "peck",
+            AnimationState::Jump => "jump",
+            AnimationState::Climb => "climb",
+            AnimationState::Fall => "fall",
+        }
+    }
+}
+
+/// Plays the clip for `state` on a sprite sheet, starting it from the first
+/// frame whenever the state changes
+#[derive(Component, Debug)]
+pub struct Animator {
+    pub clips: Handle<AnimationClips>,
+    pub overlay: Option<Handle<ClipOverlay>>,
+    pub state: AnimationState,
+    /// The state whose clip is playing, once it has started
+    playing: Option<AnimationState>,
+    /// Frames into the playing clip
+    frame: usize,
+    /// Seconds the current frame has been shown for
+    elapsed: f32,
+}
+
+impl Animator {
+    pub fn new(clips: Handle<AnimationClips>) -> Self {
+        Self {
+            clips,
+            overlay: None,
+            state: AnimationState::Idle,
+            playing: None,
+            frame: 0,
+            elapsed: 0.,
+        }
+    }
+
+    pub fn with_overlay(mut self, overlay: Handle<ClipOverlay>) -> Self {
+        self.overlay = Some(overlay);
+        self
+    }
+
+    /// Plays `clip` on by `delta` seconds, returning how far into it each
+    /// frame entered on the way is. A new state starts its clip from the
+    /// first frame.
+    fn advance(
+        &mut self,
+        clip: &AnimationClip,
+        mode: LoopMode,
+        delta: f32,
+    ) -> Vec<usize> {
+        if self.playing != Some(self.state) {
+            self.playing = Some(self.state);
+            self.frame = 0;
+            self.elapsed = 0.;
+            return vec![0];
+        }
+
+        let mut entered = Vec::new();
+        self.elapsed += delta;
+        while self.elapsed >= clip.duration(self.frame) {
+            self.elapsed -= clip.duration(self.frame);
+            let next = match mode {
+                LoopMode::Loop => (self.frame + 1) % clip.len(),
+                LoopMode::Once => (self.frame + 1).min(clip.len() - 1),
+            };
+            if next != self.frame {
+                self.frame = next;
+                entered.push(next);
+            }
+        }
+        entered
+    }
+}
+
+/// Clips edited to run off the end of their sheet are skipped with a
+/// warning, rather than crashing the renderer, until they are put right
+fn animate_sprites(
+    mut animator_query: Query<(
+        Entity,
+        &mut Animator,
+        &mut TextureAtlasSprite,
+        &Handle<TextureAtlas>,
+    )>,
+    clips: Res<Assets<AnimationClips>>,
+    overlays: Res<Assets<ClipOverlay>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    time: Res<Time>,
+    mut frame_events: EventWriter<FrameEvent>,
+    mut reported: Local<HashSet<(HandleId, String)>>,
+) {
+    for (entity, mut animator, mut sprite, atlas) in animator_query.iter_mut() {
+        let overlay = match &animator.overlay {
+            Some(handle) => match overlays.get(handle) {
+                Some(overlay) => Some(overlay),
+                // Wait for it, rather than play the wrong clip meanwhile
+                None => continue,
+            },
+            None => None,
+        };
+        let state_clip = overlay
+            .and_then(|overlay| overlay.states.get(animator.state.clip_name()));
+        let clip_name = state_clip
+            .map_or(animator.state.clip_name(), |state| state.clip.as_str());
+        let clip = match clips
+            .get(&animator.clips)
+            .and_then(|clips| clips.get(clip_name))
+        {
+            Some(clip) => clip,
+            None => continue,
+        };
+        let mode = state_clip.and_then(|state| state.mode).unwrap_or(clip.mode);
+        let overlay_events = overlay
+            .and_then(|overlay| overlay.events.get(&clip.name))
+            .map_or(&[][..], Vec::as_slice);
+        let frame_count = match atlases.get(atlas) {
+            Some(atlas) => atlas.textures.len(),
+            None => continue,
+        };
+
+        let key = (animator.clips.id, clip.name.clone());
+        match clip.problem(frame_count, overlay_events) {
+            Some(problem) => {
+                if reported.insert(key) {
+                    warn!("Skipping animation clip {}: {}", clip.name, problem);
+                }
+                continue;
+            }
+            None => {
+                reported.remove(&key);
+            }
+        }
+
+        for frame in animator.advance(clip, mode, time.delta_seconds()) {
+            for event in clip.events_on(frame, overlay_events) {
+                frame_events.send(FrameEvent {
+                    entity,
+                    name: event.name.clone(),
+                });
+            }
+        }
+
+        // An edited clip may have fewer frames than the one playing
+        let index = clip.first + animator.frame.min(clip.len() - 1);
+        if sprite.index != index {
+            sprite.index = index;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ClipOverlayLoader;
+
+impl AssetLoader for ClipOverlayLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let overlay: ClipOverlay = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(overlay));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["overlay.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sheet frames 2 to 4, each shown for a quarter second
+    fn clip(frame_durations: Vec<f32>) -> AnimationClip {
+        AnimationClip {
+            name: "walk".to_string(),
+            first: 2,
+            last: 4,
+            frame_duration: 0.25,
+            frame_durations,
+            mode: LoopMode::Loop,
+            events: vec![event(2, "start")],
+        }
+    }
+
+    fn event(frame: usize, name: &str) -> ClipEvent {
+        ClipEvent {
+            frame,
+            name: name.to_string(),
+        }
+    }
+
+    /// Starts the clip, then plays it on by four quarter seconds, giving the
+    /// frames entered each time
+    fn play(clip: &AnimationClip, mode: LoopMode) -> Vec<Vec<usize>> {
+        let mut animator = Animator::new(Handle::default());
+        (0..5)
+            .map(|step| {
+                let delta = if step == 0 { 0. } else { 0.25 };
+                animator.advance(clip, mode, delta)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loop_wraps_round_to_the_first_frame() {
+        assert_eq!(
+            play(&clip(Vec::new()), LoopMode::Loop),
+            [vec![0], vec![1], vec![2], vec![0], vec![1]]
+        );
+    }
+
+    #[test]
+    fn once_stays_on_the_last_frame() {
+        assert_eq!(
+            play(&clip(Vec::new()), LoopMode::Once),
+            [vec![0], vec![1], vec![2], vec![], vec![]]
+        );
+    }
+
+    #[test]
+    fn frames_without_their_own_duration_use_the_clip_duration() {
+        assert_eq!(
+            play(&clip(vec![0.5]), LoopMode::Loop),
+            [vec![0], vec![], vec![1], vec![2], vec![0]]
+        );
+    }
+
+    #[test]
+    fn events_fire_on_the_first_frame_and_again_on_wrapping() {
+        let clip = clip(Vec::new());
+        let overlay_events = [event(4, "end")];
+        let names: Vec<Vec<&str>> = play(&clip, LoopMode::Loop)
+            .iter()
+            .map(|frames| {
+                frames
+                    .iter()
+                    .flat_map(|frame| clip.events_on(*frame, &overlay_events))
+                    .map(|event| event.name.as_str())
+                    .collect()
+            })
+            .collect();
+
+        assert_eq!(
+            names,
+            [vec!["start"], vec![], vec!["end"], vec!["start"], vec![]]
+        );
+    }
+
+    #[test]
+    fn clips_that_dont_fit_their_sheet_have_a_problem() {
+        let clip = clip(Vec::new());
+        assert_eq!(clip.problem(5, &[]), None);
+        assert!(clip.problem(4, &[]).is_some());
+        assert!(clip.problem(5, &[event(1, "early")]).is_some());
+
+        let backwards = AnimationClip {
+            first: 4,
+            last: 2,
+            ..clip
+        };
+        assert!(backwards.problem(5, &[]).is_some());
+    }
+}
@@ -0,0 +1,137 @@
+use asefile::{AnimationDirection, AsepriteFile};
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    utils::BoxedFuture,
+};
+
+use crate::animation::{AnimationClip, AnimationClips, LoopMode};
+
+/// Loads `.aseprite` files straight from the artists' source, so there is
+/// no sprite sheet to export and no grid size to keep in step by hand
+pub struct AsepritePlugin;
+
+impl Plugin for AsepritePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Aseprite>()
+            .init_asset_loader::<AsepriteLoader>();
+    }
+}
+
+/// Everything made from one Aseprite file. The parts can also be loaded on
+/// their own with a label, as in `kiwi.aseprite#atlas`.
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "c5a1f3e7-8b2d-4a6c-9e0f-7d3b5a1c2e4f"]
+pub struct Aseprite {
+    /// Every frame side by side in one image, labelled `texture`
+    pub texture: Handle<Image>,
+    /// One cell per frame, in frame order, labelled `atlas`
+    pub atlas: Handle<TextureAtlas>,
+    /// A clip for each tag, with the frame durations set in Aseprite,
+    /// labelled `clips`. Every clip loops, and a `ClipOverlay` can play one
+    /// once instead.
+    pub clips: Handle<AnimationClips>,
+}
+
+#[derive(Default)]
+pub struct AsepriteLoader;
+
+impl AssetLoader for AsepriteLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let file = AsepriteFile::read(bytes)?;
+            let (width, height) = (file.width() as u32, file.height() as u32);
+            let frame_count = file.num_frames();
+
+            let mut sheet = image::RgbaImage::new(width * frame_count, height);
+            for frame in 0..frame_count {
+                image::imageops::replace(
+                    &mut sheet,
+                    &file.frame(frame).image(),
+                    (frame * width) as i64,
+                    0,
+                );
+            }
+            let texture = load_context.set_labeled_asset(
+                "texture",
+                LoadedAsset::new(Image::new(
+                    Extent3d {
+                        width: sheet.width(),
+                        height: sheet.height(),
+                        depth_or_array_layers: 1,
+                    },
+                    TextureDimension::D2,
+                    sheet.into_raw(),
+                    TextureFormat::Rgba8UnormSrgb,
+                )),
+            );
+
+            let atlas = load_context.set_labeled_asset(
+                "atlas",
+                LoadedAsset::new(TextureAtlas::from_grid(
+                    texture.clone(),
+                    Vec2::new(width as f32, height as f32),
+                    frame_count as usize,
+                    1,
+                )),
+            );
+
+            let clips = (0..file.num_tags())
+                .map(|index| {
+                    let tag = file.tag(index);
+                    if tag.animation_direction() != AnimationDirection::Forward
+                    {
+                        warn!(
+                            "Tag {} plays {:?}, which isn't supported, so it \
+                             will loop forwards",
+                            tag.name(),
+                            tag.animation_direction()
+                        );
+                    }
+
+                    let frames = tag.from_frame()..=tag.to_frame();
+                    let frame_durations: Vec<f32> = frames
+                        .map(|frame| {
+                            file.frame(frame).duration() as f32 / 1000.
+                        })
+                        .collect();
+
+                    AnimationClip {
+                        name: tag.name().to_string(),
+                        first: tag.from_frame() as usize,
+                        last: tag.to_frame() as usize,
+                        frame_duration: frame_durations
+                            .first()
+                            .copied()
+                            .unwrap_or(0.1),
+                        frame_durations,
+                        mode: LoopMode::Loop,
+                        // Frame events are kept in a `ClipOverlay`
+                        events: Vec::new(),
+                    }
+                })
+                .collect();
+            let clips = load_context.set_labeled_asset(
+                "clips",
+                LoadedAsset::new(AnimationClips { clips }),
+            );
+
+            load_context.set_default_asset(LoadedAsset::new(Aseprite {
+                texture,
+                atlas,
+                clips,
+            }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["aseprite", "ase"]
+    }
+}
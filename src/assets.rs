@@ -1,9 +1,17 @@
+use std::collections::HashMap;
+
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
 use bevy_ecs_ldtk::LdtkAsset;
 use bevy_kira_audio::AudioSource;
 
-use crate::statemanagement::GameState;
+use crate::{
+    animation::{AnimationClips, ClipOverlay},
+    locale::StringTable,
+    movement::KiwiMovementConfig,
+    statemanagement::GameState,
+    vocabulary::{Vocabulary, WordEntry},
+};
 
 pub struct AssetPlugin;
 
@@ -15,8 +23,13 @@ impl Plugin for AssetPlugin {
                 .with_collection::<AudioAssets>()
                 .with_collection::<FontAssets>()
                 .with_collection::<BackgroundLayerAssets>()
+                .with_collection::<SpriteAssets>()
                 .with_collection::<LevelAsset>()
-                .continue_to_state(GameState::GameIntro),
+                .with_collection::<VocabularyAssets>()
+                .with_collection::<LocaleAssets>()
+                .with_collection::<MovementAssets>()
+                .with_collection::<AnimationAssets>()
+                .continue_to_state(GameState::MainMenu),
         );
     }
 
@@ -38,6 +51,41 @@ pub struct AudioAssets {
 
     #[asset(path = "audio/music/soothing-nature-by-chilledmusic.ogg")]
     pub soothing_nature: Handle<AudioSource>,
+
+    #[asset(path = "audio/ui/move.ogg")]
+    pub ui_move: Handle<AudioSource>,
+
+    #[asset(path = "audio/ui/select.ogg")]
+    pub ui_select: Handle<AudioSource>,
+
+    /// Starts out empty once the rest have loaded, and fills up as words
+    /// are spoken
+    pub words: WordClips,
+}
+
+/// Recordings of words being spoken. There are too many to load up front
+/// with the music, and their paths are only known from the vocabulary, so
+/// each is loaded the first time its word is asked for.
+#[derive(Default)]
+pub struct WordClips {
+    clips: HashMap<String, Handle<AudioSource>>,
+}
+
+impl WordClips {
+    /// The clip for a word, starting to load it if this is the first time.
+    /// Words without a recording have no clip.
+    pub fn load(
+        &mut self,
+        entry: &WordEntry,
+        asset_server: &AssetServer,
+    ) -> Option<Handle<AudioSource>> {
+        let path = entry.pronunciation.as_ref()?;
+        let clip = self
+            .clips
+            .entry(entry.word.to_lowercase())
+            .or_insert_with(|| asset_server.load(path.as_str()));
+        Some(clip.clone())
+    }
 }
 
 #[derive(AssetCollection)]
@@ -55,8 +103,51 @@ pub struct BackgroundLayerAssets {
     pub background_layer_4: Handle<Image>,
 }
 
+#[derive(AssetCollection)]
+pub struct SpriteAssets {
+    #[asset(path = "graphics/sprites/kiwi_sprite_001.aseprite#atlas")]
+    pub kiwi: Handle<TextureAtlas>,
+
+    #[asset(path = "graphics/sprites/bug_sprite_001.aseprite#atlas")]
+    pub bug: Handle<TextureAtlas>,
+}
+
 #[derive(AssetCollection)]
 pub struct LevelAsset {
     #[asset(path = "levels/levels.ldtk")]
     pub scene: Handle<LdtkAsset>,
 }
+
+#[derive(AssetCollection)]
+pub struct VocabularyAssets {
+    #[asset(path = "vocabulary/words.vocab.ron")]
+    pub words: Handle<Vocabulary>,
+}
+
+#[derive(AssetCollection)]
+pub struct LocaleAssets {
+    #[asset(path = "locale/mi.strings.ron")]
+    pub te_reo: Handle<StringTable>,
+
+    #[asset(path = "locale/en.strings.ron")]
+    pub english: Handle<StringTable>,
+}
+
+#[derive(AssetCollection)]
+pub struct MovementAssets {
+    #[asset(path = "config/kiwi.movement.ron")]
+    pub kiwi: Handle<KiwiMovementConfig>,
+}
+
+#[derive(AssetCollection)]
+pub struct AnimationAssets {
+    #[asset(path = "graphics/sprites/kiwi_sprite_001.aseprite#clips")]
+    pub kiwi: Handle<AnimationClips>,
+    #[asset(path = "animation/kiwi.overlay.ron")]
+    pub kiwi_overlay: Handle<ClipOverlay>,
+
+    #[asset(path = "graphics/sprites/bug_sprite_001.aseprite#clips")]
+    pub bug: Handle<AnimationClips>,
+    #[asset(path = "animation/bug.overlay.ron")]
+    pub bug_overlay: Handle<ClipOverlay>,
+}
@@ -0,0 +1,161 @@
+//! Checks that the bundled fonts can draw every character used in the
+//! localisation and vocabulary assets.
+//!
+//! ```text
+//! cargo run --bin glyph-check -- [--font <ttf>] [--fallback <ttf>]...
+//! ```
+//!
+//! A character passes if the main font or any fallback font has a glyph for
+//! it. Exits with a failure status if anything is missing.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use ab_glyph::{Font, FontVec};
+use tane_game::{locale::StringTable, vocabulary::Vocabulary};
+
+const ASSETS: &str = "assets";
+const DEFAULT_FONT: &str = "assets/fonts/Baloo-Regular.ttf";
+
+struct Options {
+    font: PathBuf,
+    fallbacks: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        font: PathBuf::from(DEFAULT_FONT),
+        fallbacks: Vec::new(),
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(PathBuf::from)
+                .ok_or_else(|| format!("{} needs a font path", arg))
+        };
+        match arg.as_str() {
+            "--font" => options.font = value()?,
+            "--fallback" => options.fallbacks.push(value()?),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn load_font(path: &Path) -> Result<FontVec, String> {
+    let bytes = fs::read(path)
+        .map_err(|error| format!("can't read {}: {}", path.display(), error))?;
+    FontVec::try_from_vec(bytes)
+        .map_err(|error| format!("can't parse {}: {}", path.display(), error))
+}
+
+/// Every `.strings.ron` and `.vocab.ron` file under `dir`
+fn find_text_assets(
+    dir: &Path,
+    found: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    let entries = fs::read_dir(dir)
+        .map_err(|error| format!("can't read {}: {}", dir.display(), error))?;
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = path.to_string_lossy();
+        if path.is_dir() {
+            find_text_assets(&path, found)?;
+        } else if name.ends_with(".strings.ron") || name.ends_with(".vocab.ron")
+        {
+            found.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// The player-facing text in an asset file
+fn read_strings(path: &Path) -> Result<Vec<String>, String> {
+    let error = |error: &dyn std::fmt::Display| {
+        format!("can't parse {}: {}", path.display(), error)
+    };
+    let source = fs::read_to_string(path).map_err(|e| error(&e))?;
+
+    if path.to_string_lossy().ends_with(".strings.ron") {
+        let table: StringTable =
+            ron::from_str(&source).map_err(|e| error(&e))?;
+        Ok(table.strings.into_values().collect())
+    } else {
+        let vocabulary: Vocabulary =
+            ron::from_str(&source).map_err(|e| error(&e))?;
+        let words = vocabulary
+            .words
+            .into_iter()
+            .flat_map(|entry| [entry.word, entry.english, entry.example]);
+        let phrases = vocabulary
+            .phrases
+            .into_iter()
+            .flat_map(|phrase| [phrase.te_reo, phrase.english]);
+        Ok(words.chain(phrases).collect())
+    }
+}
+
+fn run() -> Result<bool, String> {
+    let options = parse_args()?;
+    let fonts = std::iter::once(&options.font)
+        .chain(&options.fallbacks)
+        .map(|path| load_font(path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut files = Vec::new();
+    find_text_assets(Path::new(ASSETS), &mut files)?;
+    files.sort();
+
+    let mut all_covered = true;
+    for file in &files {
+        // Each missing character, with the first string it turned up in
+        let mut missing = BTreeMap::new();
+        for text in read_strings(file)? {
+            for character in text.chars().filter(|c| !c.is_whitespace()) {
+                let covered =
+                    fonts.iter().any(|font| font.glyph_id(character).0 != 0);
+                if !covered {
+                    missing.entry(character).or_insert_with(|| text.clone());
+                }
+            }
+        }
+
+        for (character, text) in &missing {
+            println!(
+                "{}: no glyph for {:?} (U+{:04X}) in {:?}",
+                file.display(),
+                character,
+                *character as u32,
+                text
+            );
+        }
+        all_covered &= missing.is_empty();
+    }
+
+    println!(
+        "Checked {} files against {} font(s)",
+        files.len(),
+        fonts.len()
+    );
+    Ok(all_covered)
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("glyph-check: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,38 +1,46 @@
+use rand::{seq::IteratorRandom, Rng};
 use std::{collections::HashSet, time::Duration};
-use rand::seq::IteratorRandom;
 
-use bevy::{prelude::*, time::FixedTimestep};
-use bevy_ecs_ldtk::{LdtkEntity, prelude::RegisterLdtkObjects};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{prelude::RegisterLdtkObjects, LdtkEntity};
 use bevy_inspector_egui::Inspectable;
+use heron::prelude::*;
 use iyes_loopless::prelude::*;
 
-use crate::{statemanagement::{GameState, PauseState}, level::GroundDetection};
+use crate::{
+    animation::{AnimationState, Animator},
+    assets::{AnimationAssets, SpriteAssets},
+    kiwi::ColliderBundle,
+    level::{GameLayer, GroundDetection},
+    statemanagement::{GameState, PauseState},
+};
 
 pub struct BugPlugin;
 
 impl Plugin for BugPlugin {
     fn build(&self, app: &mut App) {
-        // info!("BugPlugin");
-        app
-            .insert_resource(BugSpawnTimer(Timer::new(Duration::from_secs(2), true)))
-            .register_ldtk_entity::<BugSpawnerBundle>("BugSpawner")
-            .add_system_set(
-                ConditionSet::new()
-                    .run_in_state(GameState::GamePlaying)
-                    .run_not_in_state(PauseState::Paused)
-                    .with_system(spawn_bugs)
-                    .into(),
-            )
-            // .add_system_set(
-            //     SystemSet::new()
-            //         .with_run_criteria(FixedTimestep::step(1.0))
-            //         .with_system(move_bugs)
-            //         .into()
-            // )
-            ;
+        app.insert_resource(BugSpawnTimer(Timer::new(
+            Duration::from_secs(2),
+            true,
+        )))
+        .add_event::<BugEaten>()
+        .register_ldtk_entity::<BugSpawnerBundle>("BugSpawner")
+        .add_system_set(
+            ConditionSet::new()
+                .run_in_state(GameState::GamePlaying)
+                .run_not_in_state(PauseState::Paused)
+                .with_system(spawn_bugs)
+                .with_system(crawl_bugs)
+                .with_system(eat_bugs)
+                .with_system(scatter_crumbs)
+                .into(),
+        );
     }
 }
 
+const MAX_BUGS: usize = 5;
+const CRAWL_SPEED: f32 = 20.;
+
 pub struct BugSpawnTimer(Timer);
 
 #[derive(Component, Default, Debug, Inspectable)]
@@ -49,10 +57,30 @@ pub struct Climber {
     pub intersecting_climbables: HashSet<Entity>,
 }
 
-#[derive(Component, Default, Debug)]
-pub struct Bug;
+/// Sent when the kiwi catches a bug, just before the bug is removed
+pub struct BugEaten {
+    pub bug: Entity,
+    pub position: Vec3,
+}
+
+/// A bug crawls in `direction` (-1 left, 1 right) until its turn timer runs
+/// out, when it picks a new direction at random.
+#[derive(Component, Debug)]
+pub struct Bug {
+    pub direction: f32,
+    turn_timer: Timer,
+}
 
-#[derive(Bundle, Default, LdtkEntity)]
+impl Default for Bug {
+    fn default() -> Self {
+        Self {
+            direction: 1.,
+            turn_timer: Timer::from_seconds(1.5, true),
+        }
+    }
+}
+
+#[derive(Bundle, Default)]
 pub struct BugBundle {
     bug: Bug,
 
@@ -61,40 +89,166 @@ pub struct BugBundle {
     ground_detection: GroundDetection,
 
     #[bundle]
-    #[sprite_sheet_bundle("graphics/sprites/bug_sprite_001.png", 16.0, 16.0, 5, 1, 0.0, 0.0, 0)]
     sprite_sheet: SpriteSheetBundle,
+
+    #[bundle]
+    collider_bundle: ColliderBundle,
 }
 
-/* System to spawn bugs */
-pub fn spawn_bugs(mut commands: Commands,
-                  mut bug_spawner_query: Query<&mut Transform, With<BugSpawner>>,
-                  bugs_query: Query<&mut Transform, With<Bug>>,
-                  time: Res<Time>,
-                  mut timer: ResMut<BugSpawnTimer>,
-                  ){
-    // info!("spawn bugs");
+impl BugBundle {
+    fn new(sprites: &SpriteAssets, transform: Transform) -> Self {
+        Self {
+            sprite_sheet: SpriteSheetBundle {
+                texture_atlas: sprites.bug.clone(),
+                transform,
+                ..default()
+            },
+            collider_bundle: ColliderBundle {
+                collider: CollisionShape::Cuboid {
+                    half_extends: Vec3::new(6., 4., 0.),
+                    border_radius: None,
+                },
+                rigid_body: RigidBody::Dynamic,
+                rotation_constraints: RotationConstraints::lock(),
+                collision_layers: CollisionLayers::new(
+                    GameLayer::Bug,
+                    GameLayer::World,
+                ),
+                ..default()
+            },
+            ..default()
+        }
+    }
+}
 
-    if bugs_query.iter().count() > 5 {
+/// Spawns a bug at a random `BugSpawner` every so often.
+///
+/// Bugs are added to the same layer as their spawner, so they share its
+/// coordinates and are despawned along with the level when it restarts.
+pub fn spawn_bugs(
+    mut commands: Commands,
+    bug_spawner_query: Query<(&Transform, &Parent), With<BugSpawner>>,
+    bugs_query: Query<(), With<Bug>>,
+    sprites: Res<SpriteAssets>,
+    animation_assets: Res<AnimationAssets>,
+    time: Res<Time>,
+    mut timer: ResMut<BugSpawnTimer>,
+) {
+    if !timer.0.tick(time.delta()).just_finished()
+        || bugs_query.iter().count() >= MAX_BUGS
+    {
         return;
     }
 
-    if timer.0.tick(time.delta()).just_finished() {
-        let mut rng = rand::thread_rng();
+    let mut rng = rand::thread_rng();
+    if let Some((transform, layer)) = bug_spawner_query.iter().choose(&mut rng)
+    {
+        let mut bug = BugBundle::new(&sprites, *transform);
+        bug.bug.direction = if rng.gen() { 1. } else { -1. };
+
+        commands.entity(layer.get()).with_children(|layer| {
+            layer.spawn_bundle(bug).insert(
+                Animator::new(animation_assets.bug.clone())
+                    .with_overlay(animation_assets.bug_overlay.clone()),
+            );
+        });
+    }
+}
+
+/// Bugs only push themselves along while they have ground beneath them, and
+/// otherwise fall under gravity like everything else, standing still.
+fn crawl_bugs(
+    mut bug_query: Query<(
+        &mut Bug,
+        &mut Velocity,
+        &mut TextureAtlasSprite,
+        &mut Animator,
+        &GroundDetection,
+    )>,
+    time: Res<Time>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for (mut bug, mut velocity, mut sprite, mut animator, ground_detection) in
+        bug_query.iter_mut()
+    {
+        if bug.turn_timer.tick(time.delta()).just_finished()
+            && rng.gen_bool(0.3)
+        {
+            bug.direction = -bug.direction;
+        }
 
-        let mut bug_spawners: Vec<&Transform> = bug_spawner_query.iter().choose_multiple(&mut rng, 1);
-        // info!("timer finished");
-        for (mut bug_spawner) in bug_spawners.iter_mut() {
-            // info!("Bug spawner:");// {}", bug_spawner.entity_instance);
-            commands.spawn_bundle(BugBundle::default());
+        let state = if ground_detection.on_ground {
+            velocity.linear.x = bug.direction * CRAWL_SPEED;
+            AnimationState::Walk
+        } else {
+            AnimationState::Idle
+        };
+        if animator.state != state {
+            animator.state = state;
         }
+        sprite.flip_x = bug.direction < 0.;
     }
 }
 
-// pub fn move_bugs(mut bug_query: Query<(&mut Transform), With<Bug>>){
-//     for (mut bug_transform) in bug_query.iter_mut() {
-//         // info!("Move bugs");
+/// A speck of bug left behind for a moment after it has been eaten
+#[derive(Component)]
+struct Crumb {
+    velocity: Vec2,
+    timer: Timer,
+}
 
-//         // bug_transform.translation.y += 100.0;
-//     }
-// }
+fn eat_bugs(
+    mut commands: Commands,
+    mut bug_eaten: EventReader<BugEaten>,
+    bug_query: Query<(), With<Bug>>,
+) {
+    let mut rng = rand::thread_rng();
 
+    for event in bug_eaten.iter() {
+        if bug_query.get(event.bug).is_err() {
+            continue;
+        }
+        commands.entity(event.bug).despawn_recursive();
+
+        for _ in 0..6 {
+            let angle = rng.gen_range(0.0..std::f32::consts::PI);
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgb(0.45, 0.8, 0.3),
+                        custom_size: Some(Vec2::splat(2.)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(
+                        event.position + Vec3::Z,
+                    ),
+                    ..default()
+                })
+                .insert(Crumb {
+                    velocity: Vec2::new(angle.cos(), angle.sin())
+                        * rng.gen_range(30.0..60.0),
+                    timer: Timer::from_seconds(0.4, false),
+                });
+        }
+    }
+}
+
+fn scatter_crumbs(
+    mut commands: Commands,
+    mut crumb_query: Query<(Entity, &mut Crumb, &mut Transform, &mut Sprite)>,
+    time: Res<Time>,
+) {
+    for (entity, mut crumb, mut transform, mut sprite) in crumb_query.iter_mut()
+    {
+        crumb.velocity.y -= 200. * time.delta_seconds();
+        transform.translation +=
+            crumb.velocity.extend(0.) * time.delta_seconds();
+
+        crumb.timer.tick(time.delta());
+        sprite.color.set_a(crumb.timer.percent_left());
+        if crumb.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
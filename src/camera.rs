@@ -0,0 +1,245 @@
+use bevy::{
+    prelude::*,
+    render::camera::{DepthCalculation, ScalingMode, WindowOrigin},
+    window::WindowResized,
+};
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    kiwi::Kiwi,
+    statemanagement::{GameState, PauseState},
+};
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraSettings>()
+            .init_resource::<CameraBounds>()
+            .add_startup_system(setup_camera)
+            .add_enter_system(GameState::GamePlaying, reset_camera_follow)
+            .add_system(fit_camera_to_level)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::GamePlaying)
+                    .run_not_in_state(PauseState::Paused)
+                    .with_system(follow_kiwi)
+                    .into(),
+            );
+    }
+}
+
+/// Tuning for how the camera tracks the kiwi
+pub struct CameraSettings {
+    /// Height of the view in world pixels, shrunk when the level is smaller
+    pub view_height: f32,
+    /// Half the size of the box the kiwi can move around in before the
+    /// camera starts to follow
+    pub deadzone: Vec2,
+    /// How far the camera looks ahead of the kiwi in the direction it faces
+    pub look_ahead: f32,
+    /// Roughly how many seconds the camera takes to catch up to its target
+    pub smoothing_time: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            view_height: 360.,
+            deadzone: Vec2::new(24., 32.),
+            look_ahead: 32.,
+            smoothing_time: 0.3,
+        }
+    }
+}
+
+/// Bottom left and top right corners of the current level in world space. The
+/// camera never looks beyond them.
+#[derive(Default)]
+pub struct CameraBounds(Option<(Vec2, Vec2)>);
+
+/// Per-camera state for following the kiwi
+#[derive(Component, Default)]
+pub struct CameraFollow {
+    focus: Option<Vec2>,
+    velocity: Vec2,
+}
+
+fn setup_camera(mut commands: Commands, settings: Res<CameraSettings>) {
+    debug!("Spawning camera");
+    commands
+        .spawn_bundle(Camera2dBundle {
+            projection: OrthographicProjection {
+                depth_calculation: DepthCalculation::ZDifference,
+                scaling_mode: ScalingMode::FixedVertical(settings.view_height),
+                window_origin: WindowOrigin::Center,
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, 40.0),
+            ..default()
+        })
+        .insert(CameraFollow::default());
+}
+
+fn reset_camera_follow(mut follow_query: Query<&mut CameraFollow>) {
+    for mut follow in follow_query.iter_mut() {
+        *follow = CameraFollow::default();
+    }
+}
+
+/// Recomputes the level bounds whenever a level has been placed, and the zoom
+/// whenever either the level or the window size changes, so that the view
+/// never shows anything outside the level.
+fn fit_camera_to_level(
+    mut level_events: EventReader<LevelEvent>,
+    mut resize_events: EventReader<WindowResized>,
+    mut bounds: ResMut<CameraBounds>,
+    settings: Res<CameraSettings>,
+    windows: Res<Windows>,
+    level_query: Query<(&GlobalTransform, &Handle<LdtkLevel>)>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    mut camera_query: Query<&mut OrthographicProjection, With<Camera2d>>,
+) {
+    let level_changed = level_events
+        .iter()
+        .filter(|event| matches!(event, LevelEvent::Transformed(_)))
+        .count()
+        > 0;
+    let window_resized = resize_events.iter().count() > 0;
+
+    if !level_changed && !window_resized && !settings.is_changed() {
+        return;
+    }
+
+    if level_changed {
+        // Only the selected level is ever spawned
+        for (level_transform, level_handle) in &level_query {
+            if let Some(ldtk_level) = ldtk_levels.get(level_handle) {
+                let level = &ldtk_level.level;
+                let bottom_left = level_transform.translation().truncate();
+                let size = Vec2::new(level.px_wid as f32, level.px_hei as f32);
+                bounds.0 = Some((bottom_left, bottom_left + size));
+            }
+        }
+    }
+
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let aspect_ratio = window.width() / window.height();
+
+    let mut view_height = settings.view_height;
+    if let Some((min, max)) = bounds.0 {
+        let level_size = max - min;
+        view_height = view_height
+            .min(level_size.y)
+            .min(level_size.x / aspect_ratio);
+    }
+
+    for mut projection in camera_query.iter_mut() {
+        projection.scaling_mode = ScalingMode::FixedVertical(view_height);
+    }
+}
+
+fn follow_kiwi(
+    mut camera_query: Query<
+        (&mut Transform, &OrthographicProjection, &mut CameraFollow),
+        Without<Kiwi>,
+    >,
+    kiwi_query: Query<(&GlobalTransform, &TextureAtlasSprite), With<Kiwi>>,
+    bounds: Res<CameraBounds>,
+    settings: Res<CameraSettings>,
+    time: Res<Time>,
+) {
+    let (kiwi_transform, kiwi_sprite) = match kiwi_query.get_single() {
+        Ok(kiwi) => kiwi,
+        Err(_) => return,
+    };
+    let kiwi_position = kiwi_transform.translation().truncate();
+    let facing = if kiwi_sprite.flip_x { -1. } else { 1. };
+
+    for (mut camera_transform, projection, mut follow) in
+        camera_query.iter_mut()
+    {
+        // The focus only moves once the kiwi pushes against the deadzone edge
+        let mut focus = follow.focus.unwrap_or(kiwi_position);
+        let offset = kiwi_position - focus;
+        focus += (offset.abs() - settings.deadzone).max(Vec2::ZERO)
+            * offset.signum();
+        follow.focus = Some(focus);
+
+        let half_view = Vec2::new(
+            projection.right - projection.left,
+            projection.top - projection.bottom,
+        ) * projection.scale
+            / 2.;
+
+        let target = clamp_to_level(
+            focus + Vec2::new(settings.look_ahead * facing, 0.),
+            half_view,
+            &bounds,
+        );
+
+        let (position, velocity) = smooth_damp(
+            camera_transform.translation.truncate(),
+            target,
+            follow.velocity,
+            settings.smoothing_time,
+            time.delta_seconds(),
+        );
+        follow.velocity = velocity;
+
+        let position = clamp_to_level(position, half_view, &bounds);
+        camera_transform.translation.x = position.x;
+        camera_transform.translation.y = position.y;
+    }
+}
+
+/// Keeps a view of the given half size inside the level, centring it on any
+/// axis where the level is smaller than the view.
+fn clamp_to_level(
+    centre: Vec2,
+    half_view: Vec2,
+    bounds: &CameraBounds,
+) -> Vec2 {
+    let (min, max) = match bounds.0 {
+        Some(corners) => corners,
+        None => return centre,
+    };
+
+    let clamp_axis = |value: f32, min: f32, max: f32, half: f32| {
+        if max - min <= half * 2. {
+            (min + max) / 2.
+        } else {
+            value.clamp(min + half, max - half)
+        }
+    };
+
+    Vec2::new(
+        clamp_axis(centre.x, min.x, max.x, half_view.x),
+        clamp_axis(centre.y, min.y, max.y, half_view.y),
+    )
+}
+
+/// Critically damped spring towards `target`, returning the new position and
+/// velocity. Never overshoots, so the camera settles without wobbling.
+fn smooth_damp(
+    current: Vec2,
+    target: Vec2,
+    velocity: Vec2,
+    smoothing_time: f32,
+    delta: f32,
+) -> (Vec2, Vec2) {
+    let omega = 2. / smoothing_time.max(0.0001);
+    let x = omega * delta;
+    let decay = 1. / (1. + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (velocity + omega * change) * delta;
+
+    (
+        target + (change + temp) * decay,
+        (velocity - omega * temp) * decay,
+    )
+}
@@ -0,0 +1,333 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    kiwi::KiwiAction, locale::Localised, menu::MenuAction,
+    paused::PauseMenuAction, settings::Settings, statemanagement::GameState,
+};
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Rebinding>()
+            .add_exit_system(GameState::MainMenu, stop_rebinding)
+            .add_system(apply_bindings::<KiwiAction>)
+            .add_system(apply_bindings::<PauseMenuAction>)
+            .add_system(apply_bindings::<MenuAction>)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::MainMenu)
+                    .with_system(capture_binding)
+                    .with_system(show_rebinding_prompt)
+                    .into(),
+            );
+    }
+}
+
+/// A single key or gamepad button
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButtonType),
+}
+
+impl Binding {
+    fn same_device(&self, other: &Binding) -> bool {
+        matches!(
+            (self, other),
+            (Binding::Key(_), Binding::Key(_))
+                | (Binding::Button(_), Binding::Button(_))
+        )
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Button(button) => write!(f, "{:?}", button),
+        }
+    }
+}
+
+/// An action the player can rebind, from either of the rebindable maps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlAction {
+    Kiwi(KiwiAction),
+    Pause(PauseMenuAction),
+}
+
+impl ControlAction {
+    /// Every rebindable action, in the order the controls page lists them
+    pub const ALL: [ControlAction; 9] = [
+        ControlAction::Kiwi(KiwiAction::Up),
+        ControlAction::Kiwi(KiwiAction::Down),
+        ControlAction::Kiwi(KiwiAction::Left),
+        ControlAction::Kiwi(KiwiAction::Right),
+        ControlAction::Kiwi(KiwiAction::Peck),
+        ControlAction::Kiwi(KiwiAction::Drop),
+        ControlAction::Pause(PauseMenuAction::Open),
+        ControlAction::Pause(PauseMenuAction::Close),
+        ControlAction::Pause(PauseMenuAction::ExitGame),
+    ];
+
+    /// The string table entry labelling the action and its bindings
+    pub fn key(self) -> &'static str {
+        match self {
+            ControlAction::Kiwi(KiwiAction::Up) => "action.up",
+            ControlAction::Kiwi(KiwiAction::Down) => "action.down",
+            ControlAction::Kiwi(KiwiAction::Left) => "action.left",
+            ControlAction::Kiwi(KiwiAction::Right) => "action.right",
+            ControlAction::Kiwi(KiwiAction::Peck) => "action.peck",
+            ControlAction::Kiwi(KiwiAction::Drop) => "action.drop",
+            ControlAction::Pause(PauseMenuAction::Open) => "action.pause",
+            ControlAction::Pause(PauseMenuAction::Close) => "action.resume",
+            ControlAction::Pause(PauseMenuAction::ExitGame) => "action.exit",
+        }
+    }
+}
+
+/// The saved bindings for each rebindable action map
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Controls {
+    pub kiwi: Vec<(KiwiAction, Binding)>,
+    pub pause: Vec<(PauseMenuAction, Binding)>,
+    /// Not offered on the controls page, so the menu can always be used to
+    /// put the other bindings right
+    pub menu: Vec<(MenuAction, Binding)>,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self {
+            kiwi: KiwiAction::default_bindings(),
+            pause: PauseMenuAction::default_bindings(),
+            menu: MenuAction::default_bindings(),
+        }
+    }
+}
+
+/// Action maps that can be built from the saved controls
+pub trait Rebindable: Actionlike + Copy + PartialEq {
+    fn bindings(controls: &Controls) -> &Vec<(Self, Binding)>;
+}
+
+impl Rebindable for KiwiAction {
+    fn bindings(controls: &Controls) -> &Vec<(Self, Binding)> {
+        &controls.kiwi
+    }
+}
+
+impl Rebindable for PauseMenuAction {
+    fn bindings(controls: &Controls) -> &Vec<(Self, Binding)> {
+        &controls.pause
+    }
+}
+
+impl Rebindable for MenuAction {
+    fn bindings(controls: &Controls) -> &Vec<(Self, Binding)> {
+        &controls.menu
+    }
+}
+
+impl Controls {
+    pub fn input_map<A: Rebindable>(&self) -> InputMap<A> {
+        let mut input_map = InputMap::default();
+        for (action, binding) in A::bindings(self) {
+            match binding {
+                Binding::Key(key) => input_map.insert(*key, *action),
+                Binding::Button(button) => input_map.insert(*button, *action),
+            };
+        }
+        input_map
+    }
+
+    /// The bindings for an action, written out for the controls page
+    pub fn describe(&self, action: ControlAction) -> String {
+        let bindings: Vec<String> = match action {
+            ControlAction::Kiwi(action) => bindings_for(&self.kiwi, action),
+            ControlAction::Pause(action) => bindings_for(&self.pause, action),
+        }
+        .iter()
+        .map(Binding::to_string)
+        .collect();
+
+        bindings.join(", ")
+    }
+
+    /// Another action that already uses `binding`. The kiwi and pause maps
+    /// are both live during play, so they are checked together.
+    pub fn conflict(
+        &self,
+        action: ControlAction,
+        binding: Binding,
+    ) -> Option<ControlAction> {
+        let kiwi = self
+            .kiwi
+            .iter()
+            .map(|(bound, existing)| (ControlAction::Kiwi(*bound), existing));
+        let pause = self
+            .pause
+            .iter()
+            .map(|(bound, existing)| (ControlAction::Pause(*bound), existing));
+
+        kiwi.chain(pause)
+            .find(|(bound, existing)| *bound != action && **existing == binding)
+            .map(|(bound, _)| bound)
+    }
+
+    /// Replaces an action's bindings from the same device as `binding`, so
+    /// rebinding a key leaves the gamepad button alone and vice versa
+    pub fn rebind(&mut self, action: ControlAction, binding: Binding) {
+        match action {
+            ControlAction::Kiwi(action) => {
+                rebind_in(&mut self.kiwi, action, binding)
+            }
+            ControlAction::Pause(action) => {
+                rebind_in(&mut self.pause, action, binding)
+            }
+        }
+    }
+}
+
+fn bindings_for<A: PartialEq>(
+    bindings: &[(A, Binding)],
+    action: A,
+) -> Vec<Binding> {
+    bindings
+        .iter()
+        .filter(|(bound, _)| *bound == action)
+        .map(|(_, binding)| *binding)
+        .collect()
+}
+
+fn rebind_in<A: PartialEq>(
+    bindings: &mut Vec<(A, Binding)>,
+    action: A,
+    binding: Binding,
+) {
+    bindings.retain(|(bound, existing)| {
+        *bound != action || !existing.same_device(&binding)
+    });
+    bindings.push((action, binding));
+}
+
+/// How long to wait for a new key or button before giving up
+const REBIND_SECONDS: f32 = 5.;
+
+/// The action waiting for the player to press its new key or button
+#[derive(Default)]
+pub struct Rebinding {
+    pub action: Option<ControlAction>,
+    /// The last key or button tried, if another action already uses it
+    conflict: Option<Binding>,
+    timeout: Timer,
+}
+
+impl Rebinding {
+    /// Waits for a new key or button for `action`
+    pub fn start(&mut self, action: ControlAction) {
+        *self = Rebinding {
+            action: Some(action),
+            conflict: None,
+            timeout: Timer::from_seconds(REBIND_SECONDS, false),
+        };
+    }
+
+    /// Whole seconds until rebinding gives up, for the prompt
+    fn seconds_left(&self) -> u32 {
+        (REBIND_SECONDS - self.timeout.elapsed_secs())
+            .max(0.)
+            .ceil() as u32
+    }
+}
+
+/// Text on the controls page explaining what to do next
+#[derive(Component)]
+pub struct RebindingPrompt;
+
+/// Every action map in play is rebuilt from the saved controls when it is
+/// spawned and whenever they change
+fn apply_bindings<A: Rebindable>(
+    settings: Res<Settings>,
+    mut map_query: Query<(&mut InputMap<A>, ChangeTrackers<InputMap<A>>)>,
+) {
+    for (mut input_map, tracker) in map_query.iter_mut() {
+        if settings.is_changed() || tracker.is_added() {
+            *input_map = settings.controls.input_map();
+        }
+    }
+}
+
+/// Takes the next key or gamepad button pressed as the new binding. Any key
+/// can be bound, Escape included, so rebinding is cancelled by waiting for
+/// it to time out.
+fn capture_binding(
+    mut rebinding: ResMut<Rebinding>,
+    mut settings: ResMut<Settings>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    time: Res<Time>,
+) {
+    let action = match rebinding.action {
+        Some(action) => action,
+        None => return,
+    };
+
+    // The key or button that chose the action is still just pressed on the
+    // frame rebinding starts, before the timer has run at all
+    let started = rebinding.timeout.elapsed_secs() > 0.;
+    if rebinding.timeout.tick(time.delta()).just_finished() {
+        *rebinding = Rebinding::default();
+        return;
+    }
+    if !started {
+        return;
+    }
+
+    let binding = match keys.get_just_pressed().next() {
+        Some(key) => Binding::Key(*key),
+        None => match buttons.get_just_pressed().next() {
+            Some(button) => Binding::Button(button.button_type),
+            None => return,
+        },
+    };
+
+    match settings.controls.conflict(action, binding) {
+        Some(_) => rebinding.conflict = Some(binding),
+        None => {
+            settings.controls.rebind(action, binding);
+            *rebinding = Rebinding::default();
+        }
+    }
+}
+
+fn show_rebinding_prompt(
+    rebinding: Res<Rebinding>,
+    mut prompt_query: Query<&mut Localised, With<RebindingPrompt>>,
+) {
+    let prompt = match (rebinding.action, rebinding.conflict) {
+        (Some(_), Some(binding)) => {
+            Localised::with_args("controls.conflict", vec![binding.to_string()])
+        }
+        (Some(_), None) => Localised::with_args(
+            "controls.press",
+            vec![rebinding.seconds_left().to_string()],
+        ),
+        (None, _) => Localised::new("controls.choose"),
+    };
+
+    for mut localised in prompt_query.iter_mut() {
+        if localised.key != prompt.key || localised.args != prompt.args {
+            *localised = prompt.clone();
+        }
+    }
+}
+
+fn stop_rebinding(mut rebinding: ResMut<Rebinding>) {
+    *rebinding = Rebinding::default();
+}
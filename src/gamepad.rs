@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    kiwi::KiwiAction,
+    paused::PauseMenuAction,
+    statemanagement::{GameState, PauseState},
+};
+
+/// Follows controllers as they are plugged in and out, pointing every action
+/// map at the one in use and pausing play if it is unplugged
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveGamepad>()
+            .init_resource::<StickSettings>()
+            .add_system(track_gamepads)
+            .add_system(assign_gamepad::<KiwiAction>)
+            .add_system(assign_gamepad::<PauseMenuAction>);
+    }
+}
+
+/// The controller the player is using, if any are connected
+#[derive(Debug, Default)]
+pub struct ActiveGamepad(pub Option<Gamepad>);
+
+impl ActiveGamepad {
+    /// How far the left stick is pushed across, with the deadzone removed
+    pub fn left_stick_x(
+        &self,
+        axes: &Axis<GamepadAxis>,
+        stick: &StickSettings,
+    ) -> f32 {
+        self.0
+            .and_then(|gamepad| {
+                axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+            })
+            .map_or(0., |value| stick.scale(value))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StickSettings {
+    /// How far the stick has to move, from 0 to 1, before it counts
+    pub deadzone: f32,
+}
+
+impl Default for StickSettings {
+    fn default() -> Self {
+        Self { deadzone: 0.2 }
+    }
+}
+
+impl StickSettings {
+    /// Ignores the deadzone and stretches the rest of the stick's travel
+    /// back out to the full range, so walking speed starts from zero
+    pub fn scale(&self, value: f32) -> f32 {
+        let deadzone = self.deadzone.clamp(0., 0.99);
+        let travel = ((value.abs() - deadzone) / (1. - deadzone)).clamp(0., 1.);
+        travel * value.signum()
+    }
+}
+
+fn track_gamepads(
+    mut commands: Commands,
+    mut events: EventReader<GamepadEvent>,
+    mut active: ResMut<ActiveGamepad>,
+    gamepads: Res<Gamepads>,
+    game_state: Res<CurrentState<GameState>>,
+    pause_state: Res<CurrentState<PauseState>>,
+) {
+    for event in events.iter() {
+        match event.event_type {
+            GamepadEventType::Connected => {
+                info!("Gamepad {:?} connected", event.gamepad);
+                if active.0.is_none() {
+                    active.0 = Some(event.gamepad);
+                }
+            }
+            GamepadEventType::Disconnected => {
+                info!("Gamepad {:?} disconnected", event.gamepad);
+                if active.0 != Some(event.gamepad) {
+                    continue;
+                }
+                active.0 = gamepads
+                    .iter()
+                    .find(|gamepad| **gamepad != event.gamepad)
+                    .copied();
+
+                if game_state.0 == GameState::GamePlaying
+                    && pause_state.0 == PauseState::UnPaused
+                {
+                    commands.insert_resource(NextState(PauseState::Paused));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Keeps an action map listening to the active controller, including maps
+/// that have just been spawned or rebuilt from the saved controls
+pub fn assign_gamepad<A: Actionlike>(
+    active: Res<ActiveGamepad>,
+    mut map_query: Query<&mut InputMap<A>>,
+) {
+    for mut input_map in map_query.iter_mut() {
+        if input_map.gamepad() == active.0 {
+            continue;
+        }
+        if let Some(gamepad) = active.0 {
+            input_map.set_gamepad(gamepad);
+        } else {
+            input_map.clear_gamepad();
+        }
+    }
+}
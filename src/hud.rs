@@ -0,0 +1,255 @@
+use bevy::{prelude::*, window::WindowResized};
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    assets::FontAssets,
+    bug::BugEaten,
+    helpers::despawn_entities_with,
+    locale::Localised,
+    statemanagement::{GameState, PauseState},
+    vocabulary::WordDiscovered,
+};
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelStats>()
+            .add_enter_system(GameState::GamePlaying, spawn_hud)
+            .add_exit_system(
+                GameState::GamePlaying,
+                despawn_entities_with::<Hud>,
+            )
+            .add_enter_system(PauseState::Paused, hide_hud)
+            .add_exit_system(PauseState::Paused, show_hud)
+            .add_system(reset_level_stats)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::GamePlaying)
+                    .run_not_in_state(PauseState::Paused)
+                    .with_system(tick_level_time)
+                    .with_system(count_bugs_eaten)
+                    .with_system(count_words_discovered)
+                    .into(),
+            )
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::GamePlaying)
+                    .with_system(update_hud_text)
+                    .with_system(scale_hud_text)
+                    .into(),
+            );
+    }
+}
+
+const BUG_POINTS: u32 = 10;
+const WORD_POINTS: u32 = 50;
+/// Window height the HUD font sizes are designed for
+const HUD_REFERENCE_HEIGHT: f32 = 720.;
+
+/// Running totals for the level being played
+#[derive(Default)]
+pub struct LevelStats {
+    pub score: u32,
+    pub bugs_eaten: u32,
+    pub words_learned: u32,
+    pub elapsed: f32,
+}
+
+#[derive(Default, Component)]
+struct Hud;
+
+#[derive(Component, Clone, Copy)]
+enum HudField {
+    Score,
+    BugsEaten,
+    WordsLearned,
+    LevelTime,
+}
+
+impl HudField {
+    /// The string table entry labelling the field, if it has one
+    fn key(self) -> Option<&'static str> {
+        match self {
+            HudField::Score => Some("hud.score"),
+            HudField::BugsEaten => Some("hud.bugs"),
+            HudField::WordsLearned => Some("hud.words"),
+            HudField::LevelTime => None,
+        }
+    }
+}
+
+/// Font size the text was designed with, before scaling to the window
+#[derive(Component)]
+struct HudText {
+    base_size: f32,
+}
+
+fn spawn_hud(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    windows: Res<Windows>,
+) {
+    let scale = windows
+        .get_primary()
+        .map_or(1., |window| window.height() / HUD_REFERENCE_HEIGHT);
+
+    let font = font_assets.baloo.clone();
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(10.0),
+                    left: Val::Px(0.0),
+                    ..default()
+                },
+                justify_content: JustifyContent::SpaceBetween,
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(Hud)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        ..default()
+                    },
+                    color: Color::NONE.into(),
+                    ..default()
+                })
+                .with_children(|column| {
+                    for field in [
+                        HudField::Score,
+                        HudField::BugsEaten,
+                        HudField::WordsLearned,
+                    ] {
+                        spawn_hud_field(column, &font, scale, field);
+                    }
+                });
+            spawn_hud_field(parent, &font, scale, HudField::LevelTime);
+        });
+}
+
+fn spawn_hud_field(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    scale: f32,
+    field: HudField,
+) {
+    let base_size = 28.0;
+    let mut text = parent.spawn_bundle(TextBundle {
+        text: Text::from_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: base_size * scale,
+                color: Color::WHITE,
+            },
+        ),
+        ..default()
+    });
+    text.insert(field).insert(HudText { base_size });
+    if let Some(key) = field.key() {
+        text.insert(Localised::new(key));
+    }
+}
+
+fn hide_hud(mut hud_query: Query<&mut Visibility, With<Hud>>) {
+    for mut visibility in hud_query.iter_mut() {
+        visibility.is_visible = false;
+    }
+}
+
+fn show_hud(mut hud_query: Query<&mut Visibility, With<Hud>>) {
+    for mut visibility in hud_query.iter_mut() {
+        visibility.is_visible = true;
+    }
+}
+
+/// Every (re)start of a level starts the counts again
+fn reset_level_stats(
+    mut level_events: EventReader<LevelEvent>,
+    mut stats: ResMut<LevelStats>,
+) {
+    for event in level_events.iter() {
+        if let LevelEvent::SpawnTriggered(_) = event {
+            *stats = LevelStats::default();
+        }
+    }
+}
+
+fn tick_level_time(time: Res<Time>, mut stats: ResMut<LevelStats>) {
+    stats.elapsed += time.delta_seconds();
+}
+
+fn count_bugs_eaten(
+    mut bug_eaten: EventReader<BugEaten>,
+    mut stats: ResMut<LevelStats>,
+) {
+    for _ in bug_eaten.iter() {
+        stats.bugs_eaten += 1;
+        stats.score += BUG_POINTS;
+    }
+}
+
+fn count_words_discovered(
+    mut word_discovered: EventReader<WordDiscovered>,
+    mut stats: ResMut<LevelStats>,
+) {
+    for _ in word_discovered.iter() {
+        stats.words_learned += 1;
+        stats.score += WORD_POINTS;
+    }
+}
+
+/// Labelled fields pass their count to the localised label, and the level
+/// time is written directly
+fn update_hud_text(
+    stats: Res<LevelStats>,
+    mut text_query: Query<(&HudField, &mut Text, Option<&mut Localised>)>,
+) {
+    if !stats.is_changed() {
+        return;
+    }
+
+    for (field, mut text, localised) in text_query.iter_mut() {
+        let value = match field {
+            HudField::Score => stats.score,
+            HudField::BugsEaten => stats.bugs_eaten,
+            HudField::WordsLearned => stats.words_learned,
+            HudField::LevelTime => {
+                let seconds = stats.elapsed as u32;
+                text.sections[0].value =
+                    format!("{}:{:02}", seconds / 60, seconds % 60);
+                continue;
+            }
+        };
+
+        if let Some(mut localised) = localised {
+            let args = vec![value.to_string()];
+            if localised.args != args {
+                localised.args = args;
+            }
+        }
+    }
+}
+
+fn scale_hud_text(
+    mut resize_events: EventReader<WindowResized>,
+    mut text_query: Query<(&HudText, &mut Text)>,
+) {
+    if let Some(resized) = resize_events.iter().last() {
+        let scale = resized.height / HUD_REFERENCE_HEIGHT;
+        for (hud_text, mut text) in text_query.iter_mut() {
+            text.sections[0].style.font_size = hud_text.base_size * scale;
+        }
+    }
+}
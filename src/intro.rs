@@ -1,21 +1,182 @@
 use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
 use iyes_loopless::prelude::*;
 
-use crate::{level::CameraWayPointBundle, statemanagement::GameState};
+use crate::{
+    level::{BrokenLevels, CameraWayPoint},
+    statemanagement::{GameState, PauseState},
+};
 
 pub struct IntroPlugin;
 
 impl Plugin for IntroPlugin {
     fn build(&self, app: &mut App) {
-        app.add_enter_system(GameState::GameIntro, begin_intro);
+        app.init_resource::<IntroSettings>()
+            .add_enter_system(GameState::GameIntro, begin_intro)
+            .add_exit_system(GameState::GameIntro, end_intro)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::GameIntro)
+                    .run_not_in_state(PauseState::Paused)
+                    .with_system(plan_intro_tour)
+                    .with_system(follow_intro_tour)
+                    .with_system(skip_intro)
+                    .into(),
+            );
     }
 }
 
-fn begin_intro(
+/// Timings for the camera tour that plays before the kiwi is handed control
+pub struct IntroSettings {
+    /// Seconds taken to travel from one waypoint to the next
+    pub travel_time: f32,
+    /// Seconds the camera rests on each waypoint before moving on
+    pub dwell_time: f32,
+}
+
+impl Default for IntroSettings {
+    fn default() -> Self {
+        Self {
+            travel_time: 1.5,
+            dwell_time: 0.5,
+        }
+    }
+}
+
+/// The route the camera takes through the level's `CameraWayPoint`s
+#[derive(Default)]
+struct IntroTour {
+    planned: bool,
+    stops: Vec<Vec2>,
+    leg: usize,
+    elapsed: f32,
+}
+
+fn begin_intro(mut commands: Commands) {
+    debug!("begin_intro");
+    commands.insert_resource(IntroTour::default());
+}
+
+fn end_intro(mut commands: Commands) {
+    commands.remove_resource::<IntroTour>();
+}
+
+/// Builds the tour once the level (and so its waypoints) has been placed in
+/// the world. Waypoints are visited in `WayPointOrder`, stopping at the one
+/// flagged as the `FinalWaypoint`. A level whose tour is broken by an edit
+/// while it's being played goes straight to play.
+fn plan_intro_tour(
     mut commands: Commands,
-    waypoints: Query<Entity, With<CameraWayPointBundle>>,
-    current_state: Res<CurrentState<GameState>>,
+    mut level_events: EventReader<LevelEvent>,
+    mut tour: ResMut<IntroTour>,
+    broken_levels: Res<BrokenLevels>,
+    waypoints: Query<(&CameraWayPoint, &GlobalTransform)>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
 ) {
-    debug!("begin_intro");
+    // Every event is read, so none are left over to replan the tour with
+    let level_iid = match level_events
+        .iter()
+        .filter_map(|event| match event {
+            LevelEvent::Transformed(iid) => Some(iid),
+            _ => None,
+        })
+        .last()
+    {
+        Some(level_iid) => level_iid,
+        None => return,
+    };
+
+    let mut ordered: Vec<(&CameraWayPoint, &GlobalTransform)> =
+        if broken_levels.0.contains_key(level_iid) {
+            Vec::new()
+        } else {
+            waypoints.iter().collect()
+        };
+    ordered.sort_by_key(|(waypoint, _)| waypoint.order);
+
+    let mut stops = Vec::new();
+    for (waypoint, transform) in ordered {
+        stops.push(transform.translation().truncate());
+        if waypoint.final_waypoint {
+            break;
+        }
+    }
+
+    debug!("Planned intro tour with {} stops", stops.len());
+
+    match stops.first() {
+        Some(start) => {
+            for mut camera_transform in camera_query.iter_mut() {
+                camera_transform.translation.x = start.x;
+                camera_transform.translation.y = start.y;
+            }
+        }
+        None => commands.insert_resource(NextState(GameState::GamePlaying)),
+    }
+
+    *tour = IntroTour {
+        planned: true,
+        stops,
+        ..default()
+    };
+}
+
+fn follow_intro_tour(
+    mut commands: Commands,
+    mut tour: ResMut<IntroTour>,
+    settings: Res<IntroSettings>,
+    time: Res<Time>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    if !tour.planned || tour.stops.is_empty() {
+        return;
+    }
+
+    if tour.leg + 1 >= tour.stops.len() {
+        commands.insert_resource(NextState(GameState::GamePlaying));
+        return;
+    }
+
+    tour.elapsed += time.delta_seconds();
+
+    let from = tour.stops[tour.leg];
+    let to = tour.stops[tour.leg + 1];
+    let progress = (tour.elapsed / settings.travel_time).min(1.);
+    let position = from.lerp(to, ease_in_out(progress));
+
+    for mut camera_transform in camera_query.iter_mut() {
+        camera_transform.translation.x = position.x;
+        camera_transform.translation.y = position.y;
+    }
+
+    if tour.elapsed >= settings.travel_time + settings.dwell_time {
+        tour.leg += 1;
+        tour.elapsed = 0.;
+    }
+}
+
+/// Any key press cuts straight to the final waypoint and starts play
+fn skip_intro(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    tour: Res<IntroTour>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    if keys.get_just_pressed().next().is_none() {
+        return;
+    }
+
+    debug!("Skipping intro");
+    if let Some(last) = tour.stops.last() {
+        for mut camera_transform in camera_query.iter_mut() {
+            camera_transform.translation.x = last.x;
+            camera_transform.translation.y = last.y;
+        }
+    }
     commands.insert_resource(NextState(GameState::GamePlaying));
 }
+
+/// Smoothstep easing, slow at either end of a leg and quickest in the middle
+fn ease_in_out(t: f32) -> f32 {
+    t * t * (3. - 2. * t)
+}
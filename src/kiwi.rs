@@ -1,12 +1,21 @@
+use std::collections::HashSet;
+
 use bevy::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
 use bevy_inspector_egui::{Inspectable, InspectorPlugin};
 use bevy_inspector_egui::widgets::{InspectorQuery, InspectorQuerySingle};
 use heron::prelude::*;
-use iyes_loopless::prelude::ConditionSet;
+use iyes_loopless::prelude::*;
 use leafwing_input_manager::prelude::*;
-
-use crate::level::GroundDetection;
+use serde::{Deserialize, Serialize};
+
+use crate::animation::{AnimationState, Animator, FrameEvent};
+use crate::assets::{AnimationAssets, SpriteAssets};
+use crate::bug::{Bug, BugEaten, Climber};
+use crate::controls::{Binding, Controls};
+use crate::gamepad::{ActiveGamepad, StickSettings};
+use crate::level::{Climbable, GameLayer, GroundDetection};
+use crate::movement::KiwiMovementConfig;
 use crate::statemanagement::{GameState, PauseState};
 
 pub struct KiwiPlugin;
@@ -21,9 +30,16 @@ impl Plugin for KiwiPlugin {
                     .run_not_in_state(PauseState::Paused)
                     .with_system(kiwi_peck_tracker)
                     .with_system(movement)
+                    .with_system(jump)
+                    .with_system(climb)
                     .with_system(animate_kiwi)
+                    .with_system(track_beak_frames)
+                    .with_system(spawn_beak_hitbox)
+                    .with_system(track_beak_contacts)
+                    .with_system(peck_bugs)
                     .into(),
             )
+            .add_system(add_kiwi_animator.run_not_in_state(GameState::Loading))
             // .add_plugin(InspectorPlugin::<InspectorQuerySingle<Entity, With<Kiwi>>>::new())
             .register_ldtk_entity::<KiwiBundle>("Kiwi");
     }
@@ -55,15 +71,50 @@ struct KiwiBundle {
     input_manager: KiwiInput,
 
     pub ground_detection: GroundDetection,
+
+    jump: KiwiJump,
+
+    climber: Climber,
+
+    acceleration: Acceleration,
 }
 
-#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+#[derive(
+    Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize,
+)]
 pub enum KiwiAction {
     Up,
     Down,
     Left,
     Right,
     Peck,
+    Drop,
+}
+
+impl KiwiAction {
+    pub fn default_bindings() -> Vec<(Self, Binding)> {
+        use KiwiAction::*;
+
+        vec![
+            (Left, Binding::Key(KeyCode::A)),
+            (Right, Binding::Key(KeyCode::D)),
+            (Left, Binding::Key(KeyCode::Left)),
+            (Right, Binding::Key(KeyCode::Right)),
+            (Up, Binding::Key(KeyCode::W)),
+            (Up, Binding::Key(KeyCode::Up)),
+            (Down, Binding::Key(KeyCode::S)),
+            (Down, Binding::Key(KeyCode::Down)),
+            (Peck, Binding::Key(KeyCode::Space)),
+            (Drop, Binding::Key(KeyCode::E)),
+            (Up, Binding::Button(GamepadButtonType::DPadUp)),
+            (Up, Binding::Button(GamepadButtonType::South)),
+            (Down, Binding::Button(GamepadButtonType::DPadDown)),
+            (Left, Binding::Button(GamepadButtonType::DPadLeft)),
+            (Right, Binding::Button(GamepadButtonType::DPadRight)),
+            (Peck, Binding::Button(GamepadButtonType::West)),
+            (Drop, Binding::Button(GamepadButtonType::North)),
+        ]
+    }
 }
 
 #[derive(Bundle)]
@@ -72,19 +123,13 @@ pub struct KiwiInput {
     input_manager: InputManagerBundle<KiwiAction>,
 }
 
+/// Starts with the default bindings, which are swapped for the player's
+/// saved ones as soon as the kiwi is spawned
 impl Default for KiwiInput {
     fn default() -> Self {
-        use KiwiAction::*;
-
         Self {
             input_manager: InputManagerBundle::<KiwiAction> {
-                input_map: InputMap::new([
-                    (KeyCode::A, Left),
-                    (KeyCode::D, Right),
-                    (KeyCode::Left, Left),
-                    (KeyCode::Right, Right),
-                    (KeyCode::Space, Peck),
-                ]),
+                input_map: Controls::default().input_map(),
                 ..default()
             },
         }
@@ -98,6 +143,7 @@ pub struct ColliderBundle {
     pub velocity: Velocity,
     pub rotation_constraints: RotationConstraints,
     pub physic_material: PhysicMaterial,
+    pub collision_layers: CollisionLayers,
 }
 
 impl From<EntityInstance> for ColliderBundle {
@@ -106,9 +152,15 @@ impl From<EntityInstance> for ColliderBundle {
 
         match entity_instance.identifier.as_ref() {
             "Kiwi" => ColliderBundle {
-                collider: CollisionShape::Sphere { radius: 8. },
+                collider: CollisionShape::Sphere {
+                    radius: KiwiMovementConfig::default().radius,
+                },
                 rigid_body: RigidBody::Dynamic,
                 rotation_constraints,
+                collision_layers: CollisionLayers::new(
+                    GameLayer::Kiwi,
+                    GameLayer::World,
+                ),
                 ..Default::default()
             },
             _ => ColliderBundle::default(),
@@ -154,81 +206,69 @@ enum KiwiPeckState {
 }
 
 
-#[derive(Component, Deref, DerefMut)]
-struct AnimationTimer(Timer);
-
-impl Default for AnimationTimer {
-    fn default() -> Self {
-        Self(Timer::from_seconds(0.1, true))
+/// Swaps the sprite sheet placed in LDtk for the one loaded from the
+/// Aseprite file, so edits to the art show up while the game runs, and
+/// starts the kiwi's animator
+fn add_kiwi_animator(
+    mut commands: Commands,
+    mut kiwi_query: Query<(Entity, &mut Handle<TextureAtlas>), Added<Kiwi>>,
+    animation_assets: Res<AnimationAssets>,
+    sprites: Res<SpriteAssets>,
+) {
+    for (kiwi, mut atlas) in kiwi_query.iter_mut() {
+        *atlas = sprites.kiwi.clone();
+        commands.entity(kiwi).insert(
+            Animator::new(animation_assets.kiwi.clone())
+                .with_overlay(animation_assets.kiwi_overlay.clone()),
+        );
     }
 }
 
+/// Picks the kiwi's animation from what it is doing, and turns it to face
+/// the way it is walking
 fn animate_kiwi(
     mut kiwi_query: Query<
         (
-            &mut Velocity,
+            &Velocity,
+            &KiwiPeckState,
+            &GroundDetection,
+            &Climber,
+            &mut Animator,
             &mut TextureAtlasSprite,
-            &mut KiwiPeckState,
         ),
-        With<Kiwi>
+        With<Kiwi>,
     >,
-    mut animation_timer: Local<AnimationTimer>,
-    time: Res<Time>
 ) {
-
-    for (mut velocity, mut sprite, mut peck_state) in kiwi_query.iter_mut() {
-        if *peck_state == KiwiPeckState::Pecking {
-            animation_timer.0.tick(time.delta());
-            sprite.index = 3 + ((sprite.index + if animation_timer.0.finished() {
-                animation_timer.0.reset();
-                1
-            } else {
-                0
-            }) % 3);
+    for (velocity, peck_state, ground, climber, mut animator, mut sprite) in
+        kiwi_query.iter_mut()
+    {
+        let state = if *peck_state == KiwiPeckState::Pecking {
+            AnimationState::Peck
+        } else if climber.climbing {
+            AnimationState::Climb
+        } else if !ground.on_ground && velocity.linear.y > 0. {
+            AnimationState::Jump
+        } else if !ground.on_ground {
+            AnimationState::Fall
+        } else if velocity.linear.x.abs() > 1. {
+            AnimationState::Walk
         } else {
-            sprite.index = if velocity.linear.x > 0. {
-                // info!("animate right");
-               sprite.flip_x = false;
-               animation_timer.0.tick(time.delta());
-               let index = 6 + ((sprite.index + if animation_timer.0.finished(){
-                   animation_timer.0.reset();
-                   1
-               } else {
-                   0
-               }) % 4);
-               // info!(" Sprite index: {}", index);
-               index
-            } else if velocity.linear.x < 0. {
-               // info!("animate left");
-               sprite.flip_x = true;
-               animation_timer.0.tick(time.delta());
-               let index = 6 + ((sprite.index + if animation_timer.0.finished(){
-                   animation_timer.0.reset();
-                   1
-               } else {
-                   0
-               }) % 4);
-               // info!(" Sprite index: {}", index);
-               index
-            } else {
-                0
-                // info!("animate idle if time has passed");
-                // let index = 2 + ((sprite.index + if animation_timer.0.finished(){
-                //     animation_timer.0.reset();
-                //     1
-                // }else{
-                //     0
-                // }) % 2);
-                // info!(" Sprite index: {}", index);
-                // index
-            }
+            AnimationState::Idle
+        };
+        if animator.state != state {
+            animator.state = state;
+        }
+
+        if velocity.linear.x > 1. && sprite.flip_x {
+            sprite.flip_x = false;
+        } else if velocity.linear.x < -1. && !sprite.flip_x {
+            sprite.flip_x = true;
         }
-        // info!("{}", velocity.linear.x);
     }
 }
 
 fn kiwi_peck_tracker(
-    mut kiwi_query: Query<(&mut KiwiPeckState, &mut TextureAtlasSprite, &Transform), With<Kiwi>>,
+    mut kiwi_query: Query<&mut KiwiPeckState, With<Kiwi>>,
     mut timer: Local<PeckStateTimer>,
     time: Res<Time>,
     ){
@@ -237,11 +277,10 @@ fn kiwi_peck_tracker(
     if timer.0.finished(){
 
         // info!("PeckStateTimer finished");
-        for (mut kiwi_peck_state, mut sprite, transform) in kiwi_query.iter_mut() {
+        for mut kiwi_peck_state in kiwi_query.iter_mut() {
             if *kiwi_peck_state == KiwiPeckState::Pecking {
                 // info!("Reset to idle peckstate");
                 *kiwi_peck_state = KiwiPeckState::Idle;
-                sprite.index = 0;
             }
         }
     }
@@ -250,12 +289,24 @@ fn kiwi_peck_tracker(
 
 fn movement(
     mut query: Query<
-        (&mut Velocity, &mut ActionState<KiwiAction>, &mut KiwiPeckState),
-        (With<Kiwi>, Changed<ActionState<KiwiAction>>),
-    >
+        (
+            &mut Velocity,
+            &mut ActionState<KiwiAction>,
+            &mut KiwiPeckState,
+            &GroundDetection,
+        ),
+        With<Kiwi>,
+    >,
+    gamepad: Res<ActiveGamepad>,
+    axes: Res<Axis<GamepadAxis>>,
+    stick: Res<StickSettings>,
+    config: Res<KiwiMovementConfig>,
+    time: Res<Time>,
 ) {
     // debug!("Movement");
-    for (mut velocity, mut action_state, mut peck_state) in query.iter_mut() {
+    for (mut velocity, mut action_state, mut peck_state, ground) in
+        query.iter_mut()
+    {
         // debug!("In query loop");
         let right = if action_state.pressed(KiwiAction::Right) {
             1.
@@ -268,8 +319,20 @@ fn movement(
             0.
         };
 
+        // Buttons walk at full speed, while the stick walks slower the less
+        // it is pushed
+        let direction = match right - left {
+            pressed if pressed != 0. => pressed,
+            _ => gamepad.left_stick_x(&axes, &stick),
+        };
+
         if *peck_state != KiwiPeckState::Pecking {
-            velocity.linear.x = (right - left) * 60.;
+            velocity.linear.x = config.walk(
+                velocity.linear.x,
+                direction * config.max_speed,
+                time.delta_seconds(),
+                ground.on_ground,
+            );
         }
 
         if action_state.pressed(KiwiAction::Peck) {
@@ -281,3 +344,299 @@ fn movement(
         }
     }
 }
+
+#[derive(Component, Debug)]
+struct KiwiJump {
+    /// Seconds since the kiwi was last on the ground
+    since_ground: f32,
+    /// Seconds since a jump was pressed that hasn't happened yet
+    since_press: Option<f32>,
+    /// Rising from a jump, with the button still held
+    rising: bool,
+    /// The button has been let go since the last jump, so holding it down
+    /// doesn't keep jumping
+    released: bool,
+}
+
+impl Default for KiwiJump {
+    fn default() -> Self {
+        Self {
+            since_ground: f32::INFINITY,
+            since_press: None,
+            rising: false,
+            released: true,
+        }
+    }
+}
+
+/// Jumps from the ground, or off whatever the kiwi is climbing when a
+/// direction is held with the jump. Pressing up against something climbable
+/// grabs it instead of jumping.
+fn jump(
+    mut query: Query<
+        (
+            &mut Velocity,
+            &ActionState<KiwiAction>,
+            &GroundDetection,
+            &KiwiPeckState,
+            &mut KiwiJump,
+            &mut Climber,
+        ),
+        With<Kiwi>,
+    >,
+    config: Res<KiwiMovementConfig>,
+    time: Res<Time>,
+) {
+    let settings = &config.jump;
+    let delta = time.delta_seconds();
+
+    for (
+        mut velocity,
+        action_state,
+        ground,
+        peck_state,
+        mut jump,
+        mut climber,
+    ) in query.iter_mut()
+    {
+        let held = action_state.pressed(KiwiAction::Up);
+        let sideways = action_state.pressed(KiwiAction::Left)
+            || action_state.pressed(KiwiAction::Right);
+        let grabbing = !climber.intersecting_climbables.is_empty()
+            && (held || action_state.pressed(KiwiAction::Down))
+            && !jump.rising;
+
+        if ground.on_ground && !jump.rising {
+            jump.since_ground = 0.;
+        } else {
+            jump.since_ground += delta;
+        }
+
+        if held && jump.released {
+            jump.released = false;
+            jump.since_press = Some(0.);
+        } else if !held {
+            jump.released = true;
+        }
+        jump.since_press = jump
+            .since_press
+            .map(|since| since + delta)
+            .filter(|since| *since <= settings.buffer_time);
+
+        let can_jump = if climber.climbing {
+            sideways
+        } else {
+            jump.since_ground <= settings.coyote_time && !grabbing
+        };
+
+        if jump.since_press.is_some()
+            && can_jump
+            && *peck_state != KiwiPeckState::Pecking
+        {
+            velocity.linear.y = settings.jump_speed;
+            jump.since_press = None;
+            jump.since_ground = f32::INFINITY;
+            jump.rising = true;
+            climber.climbing = false;
+        } else if jump.rising {
+            if velocity.linear.y <= 0. {
+                jump.rising = false;
+            } else if !held {
+                velocity.linear.y *= settings.release_cut;
+                jump.rising = false;
+            }
+        }
+    }
+}
+
+/// Grabs a climbable tile when up or down is pressed against it, then moves
+/// up and down it with gravity held off. The kiwi lets go once it is no
+/// longer overlapping one, whether it climbed over the top, walked away or
+/// jumped off.
+fn climb(
+    mut query: Query<
+        (
+            &mut Velocity,
+            &mut Acceleration,
+            &ActionState<KiwiAction>,
+            &KiwiJump,
+            &mut Climber,
+        ),
+        With<Kiwi>,
+    >,
+    climbable_query: Query<(), With<Climbable>>,
+    config: Res<KiwiMovementConfig>,
+) {
+    for (mut velocity, mut acceleration, action_state, jump, mut climber) in
+        query.iter_mut()
+    {
+        // Tiles are despawned with their level without their contacts ending
+        if climber
+            .intersecting_climbables
+            .iter()
+            .any(|tile| !climbable_query.contains(*tile))
+        {
+            climber
+                .intersecting_climbables
+                .retain(|tile| climbable_query.contains(*tile));
+        }
+
+        let up = action_state.pressed(KiwiAction::Up);
+        let down = action_state.pressed(KiwiAction::Down);
+        let overlapping = !climber.intersecting_climbables.is_empty();
+
+        if !climber.climbing && overlapping && up != down && !jump.rising {
+            climber.climbing = true;
+        } else if climber.climbing && !overlapping {
+            climber.climbing = false;
+        }
+
+        if climber.climbing {
+            velocity.linear.y = match (up, down) {
+                (true, false) => config.climb_speed,
+                (false, true) => -config.climb_speed,
+                _ => 0.,
+            };
+            acceleration.linear = -config.gravity_vector();
+        } else if acceleration.linear != Vec3::ZERO {
+            acceleration.linear = Vec3::ZERO;
+        }
+    }
+}
+
+/// Raised by the peck clip as the beak comes down, and can catch a bug
+const BEAK_DOWN: &str = "beak_down";
+/// Raised by the peck clip as the beak lifts again
+const BEAK_UP: &str = "beak_up";
+
+/// A sensor in front of the kiwi that exists only while it is pecking
+#[derive(Component, Default)]
+struct BeakHitbox {
+    /// The beak is down, between the peck clip's frame events
+    active: bool,
+    overlapping: HashSet<Entity>,
+    caught: HashSet<Entity>,
+}
+
+fn spawn_beak_hitbox(
+    mut commands: Commands,
+    kiwi_query: Query<
+        (Entity, &KiwiPeckState, &TextureAtlasSprite),
+        (With<Kiwi>, Changed<KiwiPeckState>),
+    >,
+    hitbox_query: Query<(Entity, &Parent), With<BeakHitbox>>,
+) {
+    for (kiwi, peck_state, sprite) in kiwi_query.iter() {
+        let existing = hitbox_query
+            .iter()
+            .find(|(_, parent)| parent.get() == kiwi)
+            .map(|(hitbox, _)| hitbox);
+
+        match (peck_state, existing) {
+            (KiwiPeckState::Pecking, None) => {
+                let facing = if sprite.flip_x { -1. } else { 1. };
+
+                commands.entity(kiwi).with_children(|builder| {
+                    builder
+                        .spawn()
+                        .insert(RigidBody::Sensor)
+                        .insert(CollisionShape::Cuboid {
+                            half_extends: Vec3::new(4., 4., 0.),
+                            border_radius: None,
+                        })
+                        .insert(Transform::from_xyz(facing * 10., -2., 0.))
+                        .insert(GlobalTransform::default())
+                        .insert(BeakHitbox::default());
+                });
+            }
+            (KiwiPeckState::Idle, Some(hitbox)) => {
+                commands.entity(hitbox).despawn_recursive();
+            }
+            _ => (),
+        }
+    }
+}
+
+fn track_beak_contacts(
+    mut hitbox_query: Query<(Entity, &mut BeakHitbox)>,
+    mut collisions: EventReader<CollisionEvent>,
+) {
+    for collision in collisions.iter() {
+        let (a, b) = collision.rigid_body_entities();
+
+        for (entity, mut hitbox) in hitbox_query.iter_mut() {
+            let other = if a == entity {
+                b
+            } else if b == entity {
+                a
+            } else {
+                continue;
+            };
+
+            match collision {
+                CollisionEvent::Started(..) => {
+                    hitbox.overlapping.insert(other);
+                }
+                CollisionEvent::Stopped(..) => {
+                    hitbox.overlapping.remove(&other);
+                }
+            }
+        }
+    }
+}
+
+/// Follows the beak up and down from the peck clip's frame events
+fn track_beak_frames(
+    mut frame_events: EventReader<FrameEvent>,
+    mut hitbox_query: Query<(&mut BeakHitbox, &Parent)>,
+) {
+    for event in frame_events.iter() {
+        let active = match event.name.as_str() {
+            BEAK_DOWN => true,
+            BEAK_UP => false,
+            _ => continue,
+        };
+
+        for (mut hitbox, parent) in hitbox_query.iter_mut() {
+            if parent.get() == event.entity && hitbox.active != active {
+                hitbox.active = active;
+            }
+        }
+    }
+}
+
+/// Catches every bug under the beak while it is down. Each bug is only
+/// caught once per peck.
+fn peck_bugs(
+    mut hitbox_query: Query<(&mut BeakHitbox, &Parent)>,
+    kiwi_query: Query<&KiwiPeckState, With<Kiwi>>,
+    bug_query: Query<&GlobalTransform, With<Bug>>,
+    mut bug_eaten: EventWriter<BugEaten>,
+) {
+    for (mut hitbox, parent) in hitbox_query.iter_mut() {
+        let pecking = matches!(
+            kiwi_query.get(parent.get()),
+            Ok(KiwiPeckState::Pecking)
+        );
+        if !pecking || !hitbox.active {
+            continue;
+        }
+
+        let newly_caught: Vec<Entity> = hitbox
+            .overlapping
+            .difference(&hitbox.caught)
+            .copied()
+            .collect();
+
+        for bug in newly_caught {
+            if let Ok(transform) = bug_query.get(bug) {
+                debug!("Caught a bug");
+                hitbox.caught.insert(bug);
+                bug_eaten.send(BugEaten {
+                    bug,
+                    position: transform.translation(),
+                });
+            }
+        }
+    }
+}
@@ -1,9 +1,6 @@
 use std::collections::{HashMap, HashSet};
 
-use bevy::{
-    prelude::*,
-    render::camera::{DepthCalculation, ScalingMode, WindowOrigin},
-};
+use bevy::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
 use bevy_inspector_egui::Inspectable;
 // use bevy_inspector_egui::Inspectable;
@@ -14,7 +11,8 @@ use iyes_loopless::prelude::*;
 
 use crate::{
     assets::LevelAsset,
-    kiwi::Kiwi,
+    bug::Climber,
+    movement::KiwiMovementConfig,
     statemanagement::{GameState, PauseState},
 };
 
@@ -30,16 +28,21 @@ impl Plugin for LevelManagerPlugin {
                 ..default()
             })
             // .add_plugin(NoCameraPlayerPlugin)
-            .add_startup_system(setup_camera)
             .add_enter_system(GameState::GameIntro, spawn_level)
             .register_ldtk_int_cell::<ForestFloorBundle>(1)
             .register_ldtk_int_cell::<BoardBundle>(2)
             .register_ldtk_int_cell::<TreeBundle>(3)
             .register_ldtk_entity::<CameraWayPointBundle>("CameraWayPoint")
+            .init_resource::<BrokenLevels>()
+            .add_system(check_camera_waypoints)
             .add_system(pause_physics_during_load)
             .add_system(spawn_wall_collision)
             .add_system(spawn_ground_sensor)
+            .add_event::<Landed>()
+            .add_event::<LeftGround>()
             .add_system(ground_detection)
+            .add_system(spawn_climbable_sensors)
+            .add_system(detect_climbables)
             .add_enter_system(PauseState::Paused, pause_physics)
             .add_exit_system(PauseState::Paused, unpause_physics)
             .add_system(restart_level);
@@ -47,7 +50,106 @@ impl Plugin for LevelManagerPlugin {
 }
 
 #[derive(Component, Default, Debug, Inspectable)]
-pub struct CameraWayPoint;
+pub struct CameraWayPoint {
+    pub order: i32,
+    pub final_waypoint: bool,
+}
+
+/// Mistakes a level designer can make when placing `CameraWayPoint`s in LDtk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WayPointError {
+    MissingField(&'static str),
+    DuplicateOrder(i32),
+    MissingOrder(i32),
+    NoWaypoints,
+    NoFinalWaypoint,
+    MultipleFinalWaypoints,
+}
+
+impl std::fmt::Display for WayPointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WayPointError::MissingField(field) => {
+                write!(f, "a CameraWayPoint has no value for {}", field)
+            }
+            WayPointError::DuplicateOrder(order) => write!(
+                f,
+                "more than one CameraWayPoint has WayPointOrder {}",
+                order
+            ),
+            WayPointError::MissingOrder(order) => write!(
+                f,
+                "no CameraWayPoint has WayPointOrder {}, orders must count up \
+                 from 0 without gaps",
+                order
+            ),
+            WayPointError::NoWaypoints => {
+                write!(f, "the level has no CameraWayPoints")
+            }
+            WayPointError::NoFinalWaypoint => {
+                write!(f, "no CameraWayPoint has FinalWaypoint ticked")
+            }
+            WayPointError::MultipleFinalWaypoints => write!(
+                f,
+                "more than one CameraWayPoint has FinalWaypoint ticked"
+            ),
+        }
+    }
+}
+
+impl TryFrom<&EntityInstance> for CameraWayPoint {
+    type Error = WayPointError;
+
+    fn try_from(entity_instance: &EntityInstance) -> Result<Self, Self::Error> {
+        let mut order = None;
+        let mut final_waypoint = None;
+
+        for field_instance in &entity_instance.field_instances {
+            match (field_instance.identifier.as_str(), &field_instance.value) {
+                ("WayPointOrder", FieldValue::Int(value)) => order = *value,
+                ("FinalWaypoint", FieldValue::Bool(value)) => {
+                    final_waypoint = Some(*value)
+                }
+                _ => (),
+            }
+        }
+
+        Ok(CameraWayPoint {
+            order: order.ok_or(WayPointError::MissingField("WayPointOrder"))?,
+            final_waypoint: final_waypoint
+                .ok_or(WayPointError::MissingField("FinalWaypoint"))?,
+        })
+    }
+}
+
+/// Checks that a level's waypoints form a single route: orders run from 0
+/// with no duplicates or gaps, and exactly one of them ends the tour.
+pub fn validate_camera_waypoints(
+    waypoints: &[CameraWayPoint],
+) -> Result<(), WayPointError> {
+    if waypoints.is_empty() {
+        return Err(WayPointError::NoWaypoints);
+    }
+
+    let mut orders = HashSet::new();
+    for waypoint in waypoints {
+        if !orders.insert(waypoint.order) {
+            return Err(WayPointError::DuplicateOrder(waypoint.order));
+        }
+    }
+
+    if let Some(missing) =
+        (0..waypoints.len() as i32).find(|order| !orders.contains(order))
+    {
+        return Err(WayPointError::MissingOrder(missing));
+    }
+
+    match waypoints.iter().filter(|w| w.final_waypoint).count() {
+        0 => Err(WayPointError::NoFinalWaypoint),
+        1 => Ok(()),
+        _ => Err(WayPointError::MultipleFinalWaypoints),
+    }
+}
 
 #[derive(Bundle, Component, Default)]
 pub struct CameraWayPointBundle {
@@ -63,137 +165,92 @@ impl LdtkEntity for CameraWayPointBundle {
         _asset_server: &AssetServer,
         _texture_atlases: &mut Assets<TextureAtlas>,
     ) -> CameraWayPointBundle {
-        println!("CameraWayPointBundle added, here are some facts:");
-        for field_instance in &entity_instance.field_instances {
-            println!(
-                "    its {} {}",
-                field_instance.identifier,
-                explain_field(&field_instance.value)
-            );
-        }
-
-        CameraWayPointBundle { ..default() }
-    }
-}
+        let waypoint = match CameraWayPoint::try_from(entity_instance) {
+            Ok(waypoint) => waypoint,
+            // Broken levels can't be started, so this only stands in while a
+            // level is edited into a broken state as it's being played
+            Err(error) => {
+                error!("Invalid CameraWayPoint: {}", error);
+                return CameraWayPointBundle::default();
+            }
+        };
 
-fn explain_field(value: &FieldValue) -> String {
-    match value {
-        FieldValue::Int(Some(i)) => format!("has an integer of {}", i),
-        FieldValue::Float(Some(f)) => format!("has a float of {}", f),
-        FieldValue::Bool(b) => format!("is {}", b),
-        FieldValue::String(Some(s)) => format!("says {}", s),
-        FieldValue::Color(c) => format!("has the color {:?}", c),
-        FieldValue::Enum(Some(e)) => format!("is the variant {}", e),
-        FieldValue::FilePath(Some(f)) => format!("references {}", f),
-        FieldValue::Point(Some(p)) => format!("is at ({}, {})", p.x, p.y),
-        a => format!("is hard to explain: {:?}", a),
+        CameraWayPointBundle { waypoint }
     }
 }
 
-fn setup_camera(mut commands: Commands) {
-    debug!("Spawning camera");
-    commands.spawn_bundle(Camera2dBundle {
-        projection: OrthographicProjection {
-            depth_calculation: DepthCalculation::ZDifference,
-            scaling_mode: ScalingMode::None,
-            window_origin: WindowOrigin::Center,
-            scale: 0.43,
-            left: -640.0, right: 640.0,
-            bottom: -420.0, top: 420.0,
-            ..default()
-        },
-        transform: Transform::from_xyz(0.0,0.0,40.0),
-        ..default()
-    })
-    // .insert(FlyCam)
-        ;
-}
-
-fn normalise_camera_within_level(
-    mut camera_query: Query<
-        (
-            &mut bevy::render::camera::OrthographicProjection,
-            &mut Transform,
-        ),
-        (Without<Kiwi>, With<Camera2d>),
-    >,
-    player_query: Query<&Transform, With<Kiwi>>,
-    level_query: Query<
-        (&Transform, &Handle<LdtkLevel>),
-        (Without<OrthographicProjection>, Without<Kiwi>),
-    >,
-    level_selection: Res<LevelSelection>,
-    ldtk_levels: Res<Assets<LdtkLevel>>,
-    windows: Res<Windows>,
+/// Levels whose camera tour failed to validate, by iid, with what is wrong
+/// with them. The menu refuses to start them and says why.
+#[derive(Default)]
+pub struct BrokenLevels(pub HashMap<String, WayPointError>);
+
+/// Checks the camera tour of every level in an LDtk project as soon as it
+/// arrives, rather than waiting for a player to reach it. A half-edited level
+/// saved while the game is running is marked broken instead of bringing the
+/// game down, and can be played again once it is fixed.
+fn check_camera_waypoints(
+    mut asset_events: EventReader<AssetEvent<LdtkAsset>>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    mut broken_levels: ResMut<BrokenLevels>,
 ) {
-    debug!("Normalise camera in level");
-    let window = windows.primary();
-    let aspect_ratio: f32 = window.width() / window.height();
-
-    if let Ok(Transform {
-        translation: player_translation,
-        ..
-    }) = player_query.get_single()
-    {
-        let player_translation = *player_translation;
-
-        let (mut orthographic_projection, mut camera_transform) =
-            camera_query.single_mut();
-
-        for (level_transform, level_handle) in &level_query {
-            if let Some(ldtk_level) = ldtk_levels.get(level_handle) {
-                let level = &ldtk_level.level;
-                if level_selection.is_match(&0, level) {
-                    let level_ratio =
-                        level.px_wid as f32 / ldtk_level.level.px_hei as f32;
-
-                    orthographic_projection.scaling_mode =
-                        bevy::render::camera::ScalingMode::None;
-                    orthographic_projection.bottom = 0.;
-                    orthographic_projection.left = 0.;
-                    if level_ratio > aspect_ratio {
-                        orthographic_projection.top =
-                            (level.px_hei as f32 / 9.).round() * 9.;
-                        orthographic_projection.right =
-                            orthographic_projection.top * aspect_ratio;
-                        camera_transform.translation.x = (player_translation.x
-                            - level_transform.translation.x
-                            - orthographic_projection.right / 2.)
-                            .clamp(
-                                0.,
-                                level.px_wid as f32
-                                    - orthographic_projection.right,
-                            );
-                        camera_transform.translation.y = 0.;
-                    } else {
-                        orthographic_projection.right =
-                            (level.px_wid as f32 / 16.).round() * 16.;
-                        orthographic_projection.top =
-                            orthographic_projection.right / aspect_ratio;
-                        camera_transform.translation.y = (player_translation.y
-                            - level_transform.translation.y
-                            - orthographic_projection.top / 2.)
-                            .clamp(
-                                0.,
-                                level.px_hei as f32
-                                    - orthographic_projection.top,
-                            );
-                        camera_transform.translation.x = 0.;
-                    }
-
-                    camera_transform.translation.x +=
-                        level_transform.translation.x;
-                    camera_transform.translation.y +=
-                        level_transform.translation.y;
+    for event in asset_events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle }
+            | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+
+        let ldtk_asset = match ldtk_assets.get(handle) {
+            Some(ldtk_asset) => ldtk_asset,
+            None => continue,
+        };
+
+        for level in &ldtk_asset.project.levels {
+            let result = level
+                .layer_instances
+                .iter()
+                .flatten()
+                .flat_map(|layer| &layer.entity_instances)
+                .filter(|entity| entity.identifier == "CameraWayPoint")
+                .map(CameraWayPoint::try_from)
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|waypoints| validate_camera_waypoints(&waypoints));
+
+            match result {
+                Ok(()) => {
+                    broken_levels.0.remove(&level.iid);
+                }
+                Err(error) => {
+                    error!(
+                        "Level {} can't be played: {}",
+                        level.identifier, error
+                    );
+                    broken_levels.0.insert(level.iid.clone(), error);
                 }
             }
         }
     }
 }
 
+/// Physics groups, so that the kiwi and bugs run into the level but pass
+/// through each other. Ground sensors only feel the level, so nothing stands
+/// on a kiwi or a bug. Anything left on the default layers, like walls,
+/// touches everything.
+#[derive(PhysicsLayer)]
+pub enum GameLayer {
+    World,
+    Kiwi,
+    Bug,
+    GroundSensor,
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
 pub struct Wall;
 
+/// One of the merged colliders spawned for a level's walls
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct WallCollider;
+
 #[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
 pub struct WallBundle {
     wall: Wall,
@@ -212,10 +269,15 @@ pub struct ForestFloorBundle {
     ground: Wall,
 }
 
+/// A puzzle board cell, where word tiles can be dropped
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Board;
+
 #[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
 pub struct BoardBundle {
     ground: Wall,
     climbable: Climbable,
+    board: Board,
 }
 
 #[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
@@ -257,6 +319,7 @@ pub fn spawn_wall_collision(
     parent_query: Query<&Parent, Without<Wall>>,
     level_query: Query<(Entity, &Handle<LdtkLevel>)>,
     levels: Res<Assets<LdtkLevel>>,
+    movement_config: Res<KiwiMovementConfig>,
 ) {
     /// Represents a wide wall that is 1 tile tall
     /// Used to spawn wall collisions
@@ -414,9 +477,10 @@ pub fn spawn_wall_collision(
                             })
                             .insert(RigidBody::Static)
                             .insert(PhysicMaterial {
-                                friction: 0.1,
+                                friction: movement_config.friction,
                                 ..Default::default()
                             })
+                            .insert(WallCollider)
                             .insert(Transform::from_xyz(
                                 (wall_rect.left + wall_rect.right + 1) as f32
                                     * grid_size as f32
@@ -456,6 +520,10 @@ pub fn spawn_ground_sensor(
                     .spawn()
                     .insert(RigidBody::Sensor)
                     .insert(detector_shape)
+                    .insert(CollisionLayers::new(
+                        GameLayer::GroundSensor,
+                        GameLayer::World,
+                    ))
                     .insert(Transform::from_translation(sensor_translation))
                     .insert(GlobalTransform::default())
                     .insert(GroundSensor {
@@ -479,6 +547,10 @@ pub fn spawn_ground_sensor(
                     .spawn()
                     .insert(RigidBody::Sensor)
                     .insert(detector_shape)
+                    .insert(CollisionLayers::new(
+                        GameLayer::GroundSensor,
+                        GameLayer::World,
+                    ))
                     .insert(Transform::from_translation(sensor_translation))
                     .insert(GlobalTransform::default())
                     .insert(GroundSensor {
@@ -490,47 +562,127 @@ pub fn spawn_ground_sensor(
     }
 }
 
+/// Sent when something with `GroundDetection` comes down onto the ground
+pub struct Landed {
+    pub entity: Entity,
+}
+
+/// Sent when something with `GroundDetection` walks off or jumps from the
+/// ground
+pub struct LeftGround {
+    pub entity: Entity,
+}
+
+/// Routes each contact to the ground sensor involved, in a single pass over
+/// the collision events, then updates every detector from its sensor.
+/// Anything that has been despawned no longer counts as ground.
 pub fn ground_detection(
     mut ground_detectors: Query<&mut GroundDetection>,
-    mut ground_sensors: Query<(Entity, &mut GroundSensor)>,
+    mut ground_sensors: Query<&mut GroundSensor>,
     mut collisions: EventReader<CollisionEvent>,
     rigid_bodies: Query<&RigidBody>,
+    mut landed: EventWriter<Landed>,
+    mut left_ground: EventWriter<LeftGround>,
 ) {
-    for (entity, mut ground_sensor) in ground_sensors.iter_mut() {
-        for collision in collisions.iter() {
+    for collision in collisions.iter() {
+        let (a, b) = collision.rigid_body_entities();
+
+        for (sensor, other) in [(a, b), (b, a)] {
+            let mut ground_sensor = match ground_sensors.get_mut(sensor) {
+                Ok(ground_sensor) => ground_sensor,
+                Err(_) => continue,
+            };
+
             match collision {
-                CollisionEvent::Started(a, b) => {
-                    match rigid_bodies.get(b.rigid_body_entity()) {
-                        Ok(RigidBody::Sensor) => {
-                            // don't consider sensors to be "the ground"
-                        }
-                        Ok(_) => {
-                            if a.rigid_body_entity() == entity {
-                                ground_sensor
-                                    .intersecting_ground_entities
-                                    .insert(b.rigid_body_entity());
-                            }
-                        }
-                        Err(_) => {
-                            panic!("If there's a collision, there should be an entity")
-                        }
-                    }
-                }
-                CollisionEvent::Stopped(a, b) => {
-                    if a.rigid_body_entity() == entity {
+                CollisionEvent::Started(..) => match rigid_bodies.get(other) {
+                    // Sensors aren't "the ground"
+                    Ok(RigidBody::Sensor) => (),
+                    Ok(_) => {
                         ground_sensor
                             .intersecting_ground_entities
-                            .remove(&b.rigid_body_entity());
+                            .insert(other);
                     }
+                    Err(_) => debug!(
+                        "Ignoring ground contact with {:?}, which has no \
+                         rigid body",
+                        other
+                    ),
+                },
+                CollisionEvent::Stopped(..) => {
+                    ground_sensor.intersecting_ground_entities.remove(&other);
                 }
             }
         }
+    }
 
-        if let Ok(mut ground_detection) =
-            ground_detectors.get_mut(ground_sensor.ground_detection_entity)
+    for mut ground_sensor in ground_sensors.iter_mut() {
+        if ground_sensor
+            .intersecting_ground_entities
+            .iter()
+            .any(|ground| !rigid_bodies.contains(*ground))
         {
-            ground_detection.on_ground =
-                !ground_sensor.intersecting_ground_entities.is_empty();
+            ground_sensor
+                .intersecting_ground_entities
+                .retain(|ground| rigid_bodies.contains(*ground));
+        }
+
+        let entity = ground_sensor.ground_detection_entity;
+        let on_ground = !ground_sensor.intersecting_ground_entities.is_empty();
+        if let Ok(mut ground_detection) = ground_detectors.get_mut(entity) {
+            if ground_detection.on_ground == on_ground {
+                continue;
+            }
+            ground_detection.on_ground = on_ground;
+
+            if on_ground {
+                landed.send(Landed { entity });
+            } else {
+                left_ground.send(LeftGround { entity });
+            }
+        }
+    }
+}
+
+/// Gives each climbable tile a sensor a little wider than the tile, so a
+/// climber beside it overlaps it, but a little shorter, so one standing on
+/// top doesn't
+pub fn spawn_climbable_sensors(
+    mut commands: Commands,
+    climbable_query: Query<Entity, Added<Climbable>>,
+) {
+    for entity in climbable_query.iter() {
+        commands.entity(entity).insert(RigidBody::Sensor).insert(
+            CollisionShape::Cuboid {
+                half_extends: Vec3::new(10., 7., 0.),
+                border_radius: None,
+            },
+        );
+    }
+}
+
+/// Keeps track of the climbable tiles each climber is overlapping
+pub fn detect_climbables(
+    mut climbers: Query<&mut Climber>,
+    climbables: Query<(), With<Climbable>>,
+    mut collisions: EventReader<CollisionEvent>,
+) {
+    for collision in collisions.iter() {
+        let (a, b) = collision.rigid_body_entities();
+
+        for (climber, climbable) in [(a, b), (b, a)] {
+            if !climbables.contains(climbable) {
+                continue;
+            }
+            if let Ok(mut climber) = climbers.get_mut(climber) {
+                match collision {
+                    CollisionEvent::Started(..) => {
+                        climber.intersecting_climbables.insert(climbable);
+                    }
+                    CollisionEvent::Stopped(..) => {
+                        climber.intersecting_climbables.remove(&climbable);
+                    }
+                }
+            }
         }
     }
 }
@@ -578,3 +730,58 @@ fn restart_level(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waypoint(order: i32, final_waypoint: bool) -> CameraWayPoint {
+        CameraWayPoint {
+            order,
+            final_waypoint,
+        }
+    }
+
+    #[test]
+    fn valid_waypoints_pass() {
+        let waypoints =
+            [waypoint(1, false), waypoint(0, false), waypoint(2, true)];
+        assert_eq!(validate_camera_waypoints(&waypoints), Ok(()));
+    }
+
+    #[test]
+    fn duplicate_order_is_rejected() {
+        let waypoints =
+            [waypoint(0, false), waypoint(1, false), waypoint(1, true)];
+        assert_eq!(
+            validate_camera_waypoints(&waypoints),
+            Err(WayPointError::DuplicateOrder(1))
+        );
+    }
+
+    #[test]
+    fn gap_in_orders_is_rejected() {
+        let waypoints = [waypoint(0, false), waypoint(2, true)];
+        assert_eq!(
+            validate_camera_waypoints(&waypoints),
+            Err(WayPointError::MissingOrder(1))
+        );
+    }
+
+    #[test]
+    fn missing_final_waypoint_is_rejected() {
+        let waypoints = [waypoint(0, false), waypoint(1, false)];
+        assert_eq!(
+            validate_camera_waypoints(&waypoints),
+            Err(WayPointError::NoFinalWaypoint)
+        );
+    }
+
+    #[test]
+    fn level_without_waypoints_is_rejected() {
+        assert_eq!(
+            validate_camera_waypoints(&[]),
+            Err(WayPointError::NoWaypoints)
+        );
+    }
+}
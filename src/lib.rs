@@ -1,35 +1,65 @@
 #![allow(clippy::type_complexity, clippy::too_many_arguments)]
-use bevy::prelude::*;
+use bevy::{asset::AssetServerSettings, prelude::*};
 pub use bevy_asset_loader::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
 use bevy_kira_audio::AudioPlugin;
-use heron::{Gravity, PhysicsPlugin};
+use heron::PhysicsPlugin;
 pub use iyes_loopless::prelude::*;
 
 pub const LAUNCHER_TITLE: &str = "Tane Mahuta";
 
+mod animation;
+mod aseprite;
 mod assets;
-// mod bug;
+mod camera;
+mod controls;
+mod bug;
 mod debug;
+mod gamepad;
 mod helpers;
+mod hud;
 mod intro;
 mod kiwi;
 mod level;
+pub mod locale;
+mod menu;
+mod mixer;
+mod movement;
 mod music;
 mod paused;
+mod pronunciation;
+mod puzzle;
 mod render;
+pub mod settings;
 mod statemanagement;
+mod touch;
+pub mod vocabulary;
 
+use animation::AnimationPlugin;
+use aseprite::AsepritePlugin;
 use assets::AssetPlugin;
-// use bug::BugPlugin;
+use camera::CameraPlugin;
+use controls::ControlsPlugin;
+use bug::BugPlugin;
 use debug::DebugPlugin;
+use gamepad::GamepadPlugin;
+use hud::HudPlugin;
 use intro::IntroPlugin;
 use kiwi::KiwiPlugin;
 use level::LevelManagerPlugin;
+use locale::LocalePlugin;
+use menu::MenuPlugin;
+use mixer::MixerPlugin;
+use movement::MovementPlugin;
 use music::MusicPlugin;
 use paused::PausePlugin;
+use pronunciation::PronunciationPlugin;
+use puzzle::PuzzlePlugin;
 use render::RenderPlugin;
+use settings::SettingsPlugin;
 use statemanagement::{GameState, PauseState};
+use touch::TouchControlsPlugin;
+use vocabulary::VocabularyPlugin;
 
 pub fn app() -> App {
     let mut app = App::new();
@@ -41,19 +71,39 @@ pub fn app() -> App {
             fit_canvas_to_parent: true,
             ..default()
         })
+        // Reload assets as they are edited, so data can be tuned while the
+        // game is running
+        .insert_resource(AssetServerSettings {
+            watch_for_changes: cfg!(not(target_arch = "wasm32")),
+            ..default()
+        })
         .add_plugins(DefaultPlugins)
         .add_plugin(LdtkPlugin)
         .add_plugin(PhysicsPlugin::default())
-        .insert_resource(Gravity::from(Vec3::new(0., -400., 0.)))
+        .add_plugin(MovementPlugin)
+        .add_plugin(VocabularyPlugin)
+        .add_plugin(LocalePlugin)
+        .add_plugin(AsepritePlugin)
         .add_plugin(AssetPlugin)
         .add_plugin(MusicPlugin)
         .add_plugin(AudioPlugin)
+        .add_plugin(MixerPlugin)
+        .add_plugin(SettingsPlugin)
+        .add_plugin(ControlsPlugin)
+        .add_plugin(GamepadPlugin)
+        .add_plugin(TouchControlsPlugin)
         .add_plugin(LevelManagerPlugin)
+        .add_plugin(CameraPlugin)
         .add_plugin(PausePlugin)
         // .add_plugin(DebugPlugin)
+        .add_plugin(AnimationPlugin)
         .add_plugin(KiwiPlugin)
-        // .add_plugin(BugPlugin)
+        .add_plugin(BugPlugin)
+        .add_plugin(MenuPlugin)
         .add_plugin(IntroPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(PronunciationPlugin)
+        .add_plugin(PuzzlePlugin)
         .add_plugin(RenderPlugin);
     app
 }
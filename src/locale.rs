@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::assets::LocaleAssets;
+
+pub struct LocalePlugin;
+
+impl Plugin for LocalePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<StringTable>()
+            .init_asset_loader::<StringTableLoader>()
+            .init_resource::<Locale>()
+            .add_system_to_stage(CoreStage::PostUpdate, localise_text);
+    }
+}
+
+/// Which language the interface is shown in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Language {
+    TeReo,
+    English,
+    /// Te reo first, with the English alongside
+    Bilingual,
+}
+
+impl Language {
+    /// The language after this one, for cycling through them from a menu
+    pub fn next(self) -> Self {
+        match self {
+            Language::TeReo => Language::English,
+            Language::English => Language::Bilingual,
+            Language::Bilingual => Language::TeReo,
+        }
+    }
+
+    /// The language's name for itself
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::TeReo => "Te reo Māori",
+            Language::English => "English",
+            Language::Bilingual => "Reorua / Bilingual",
+        }
+    }
+}
+
+pub struct Locale {
+    pub language: Language,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            language: Language::Bilingual,
+        }
+    }
+}
+
+/// Keyed interface strings for one language, loaded from a `.strings.ron`
+/// file. A `{}` in a string is filled in from `Localised::args`.
+#[derive(Debug, Clone, Default, Deserialize, TypeUuid)]
+#[uuid = "2d7f0a6e-93c1-4b7e-8f1d-6a4c3e5b9d02"]
+pub struct StringTable {
+    pub strings: HashMap<String, String>,
+}
+
+/// Tags a `Text` whose first section is looked up by key in the current
+/// language's string table, and kept up to date when the language changes
+#[derive(Component, Debug, Clone, Default)]
+pub struct Localised {
+    pub key: String,
+    pub args: Vec<String>,
+}
+
+impl Localised {
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn with_args(key: &str, args: Vec<String>) -> Self {
+        Self {
+            key: key.to_string(),
+            args,
+        }
+    }
+}
+
+impl Locale {
+    /// Looks a key up in the current language. Te reo falls back to English
+    /// for strings that haven't been translated yet, and anything missing
+    /// from both shows the key itself so it is easy to spot.
+    pub fn text(
+        &self,
+        key: &str,
+        args: &[String],
+        locale_assets: &LocaleAssets,
+        tables: &Assets<StringTable>,
+    ) -> String {
+        let lookup = |handle: &Handle<StringTable>| {
+            tables
+                .get(handle)
+                .and_then(|table| table.strings.get(key))
+                .map(|text| fill(text, args))
+        };
+        let te_reo = lookup(&locale_assets.te_reo);
+        let english = lookup(&locale_assets.english);
+
+        let text = match (self.language, te_reo, english) {
+            (Language::English, _, Some(english)) => Some(english),
+            (Language::TeReo, Some(te_reo), _) => Some(te_reo),
+            (Language::Bilingual, Some(te_reo), Some(english))
+                if te_reo != english =>
+            {
+                Some(format!("{} / {}", te_reo, english))
+            }
+            (_, te_reo, english) => te_reo.or(english),
+        };
+
+        text.unwrap_or_else(|| key.to_string())
+    }
+}
+
+/// Replaces each `{}` in turn with the next argument
+fn fill(text: &str, args: &[String]) -> String {
+    let mut args = args.iter();
+    let mut pieces = text.split("{}");
+    let mut filled = pieces.next().unwrap_or_default().to_string();
+
+    for piece in pieces {
+        filled.push_str(args.next().map_or("", String::as_str));
+        filled.push_str(piece);
+    }
+
+    filled
+}
+
+/// Relabels every tagged text when the language or a string table changes,
+/// and otherwise only the ones that are new or whose key or args changed.
+fn localise_text(
+    locale: Res<Locale>,
+    locale_assets: Option<Res<LocaleAssets>>,
+    tables: Res<Assets<StringTable>>,
+    mut table_events: EventReader<AssetEvent<StringTable>>,
+    mut text_query: Query<(&Localised, &mut Text, ChangeTrackers<Localised>)>,
+) {
+    let locale_assets = match locale_assets {
+        Some(locale_assets) => locale_assets,
+        None => return,
+    };
+    let relabel_all = locale.is_changed() || table_events.iter().count() > 0;
+
+    for (localised, mut text, tracker) in text_query.iter_mut() {
+        if !relabel_all && !tracker.is_changed() {
+            continue;
+        }
+
+        let value = locale.text(
+            &localised.key,
+            &localised.args,
+            &locale_assets,
+            &tables,
+        );
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct StringTableLoader;
+
+impl AssetLoader for StringTableLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let table: StringTable = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(table));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["strings.ron"]
+    }
+}
@@ -0,0 +1,782 @@
+use bevy::{app::AppExit, ecs::system::EntityCommands, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_kira_audio::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::{AudioAssets, FontAssets, LevelAsset},
+    controls::{Binding, ControlAction, Controls, Rebinding, RebindingPrompt},
+    gamepad::assign_gamepad,
+    helpers::despawn_entities_with,
+    level::BrokenLevels,
+    locale::Localised,
+    mixer::{Channel, ChannelLevel, UiChannel},
+    puzzle::PuzzleSolved,
+    settings::{Settings, WindowSetting},
+    statemanagement::GameState,
+    LAUNCHER_TITLE,
+};
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(InputManagerPlugin::<MenuAction>::default())
+            .init_resource::<GameProgress>()
+            .add_startup_system(spawn_menu_input)
+            .add_enter_system(GameState::MainMenu, open_main_menu)
+            .add_exit_system(
+                GameState::MainMenu,
+                despawn_entities_with::<MenuItem>,
+            )
+            .add_exit_system(GameState::MainMenu, close_menu)
+            .add_enter_system(GameState::GamePlaying, record_progress)
+            .add_system(unlock_next_level)
+            .add_system(assign_gamepad::<MenuAction>)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::MainMenu)
+                    .with_system(refresh_settings_pages)
+                    .with_system(refresh_level_pages)
+                    .with_system(spawn_menu_page)
+                    .with_system(navigate_menu)
+                    .with_system(click_menu_options)
+                    .with_system(highlight_menu_options)
+                    .into(),
+            );
+    }
+}
+
+/// String table keys for the lines of the credits page
+const CREDITS: &[&str] = &[
+    "credits.music",
+    "credits.music_by",
+    "credits.music_licence",
+    "credits.fonts",
+    "credits.fonts_by",
+];
+
+#[derive(
+    Actionlike, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub enum MenuAction {
+    Up,
+    Down,
+    Select,
+    Back,
+}
+
+impl MenuAction {
+    pub fn default_bindings() -> Vec<(Self, Binding)> {
+        use MenuAction::*;
+
+        vec![
+            (Up, Binding::Key(KeyCode::Up)),
+            (Up, Binding::Key(KeyCode::W)),
+            (Down, Binding::Key(KeyCode::Down)),
+            (Down, Binding::Key(KeyCode::S)),
+            (Select, Binding::Key(KeyCode::Return)),
+            (Select, Binding::Key(KeyCode::Space)),
+            (Back, Binding::Key(KeyCode::Escape)),
+            (Back, Binding::Key(KeyCode::Back)),
+            (Up, Binding::Button(GamepadButtonType::DPadUp)),
+            (Down, Binding::Button(GamepadButtonType::DPadDown)),
+            (Select, Binding::Button(GamepadButtonType::South)),
+            (Select, Binding::Button(GamepadButtonType::Start)),
+            (Back, Binding::Button(GamepadButtonType::East)),
+        ]
+    }
+}
+
+/// How far the player has got, used to offer "Continue" from the menu and to
+/// lock levels that haven't been reached yet
+#[derive(Default)]
+pub struct GameProgress {
+    pub last_level: Option<usize>,
+    pub unlocked_level: usize,
+}
+
+#[derive(Default, Component)]
+struct MenuItem;
+
+/// Marks the text of the option at this position on the current page
+#[derive(Component)]
+struct MenuOptionText(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuPage {
+    Main,
+    LevelSelect,
+    Settings,
+    Controls,
+    Credits,
+}
+
+impl MenuPage {
+    /// The page that going back leads to
+    fn parent(self) -> MenuPage {
+        match self {
+            MenuPage::Controls => MenuPage::Settings,
+            _ => MenuPage::Main,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuChoice {
+    Start,
+    Continue,
+    LevelSelect,
+    Settings,
+    Credits,
+    Quit,
+    Language,
+    Volume(Channel),
+    Window,
+    Controls,
+    Rebind(ControlAction),
+    ResetControls,
+    PlayLevel(usize),
+    Back,
+}
+
+/// Options are labelled from the string tables, apart from level names which
+/// come straight from the level file
+enum MenuLabel {
+    Key(Localised),
+    Plain(String),
+}
+
+struct MenuOption {
+    label: MenuLabel,
+    choice: MenuChoice,
+    enabled: bool,
+}
+
+/// The page currently shown, any notices above its options, and the options
+struct Menu {
+    page: MenuPage,
+    notices: Vec<Localised>,
+    options: Vec<MenuOption>,
+}
+
+/// A level as the menu lists it. A level that failed to validate can't be
+/// started, and the menu shows what is wrong with it.
+struct LevelListing {
+    name: String,
+    problem: Option<String>,
+}
+
+/// Which option on the current page is highlighted
+struct MenuCursor(usize);
+
+fn spawn_menu_input(mut commands: Commands, settings: Res<Settings>) {
+    commands.spawn_bundle(InputManagerBundle {
+        input_map: settings.controls.input_map::<MenuAction>(),
+        action_state: ActionState::default(),
+    });
+}
+
+fn open_main_menu(
+    mut commands: Commands,
+    progress: Res<GameProgress>,
+    settings: Res<Settings>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    level_asset: Res<LevelAsset>,
+    broken_levels: Res<BrokenLevels>,
+) {
+    let levels = level_listings(&ldtk_assets, &level_asset, &broken_levels);
+    commands.insert_resource(menu_page(
+        MenuPage::Main,
+        &progress,
+        &levels,
+        &settings,
+    ));
+    commands.insert_resource(MenuCursor(0));
+}
+
+fn close_menu(mut commands: Commands) {
+    commands.remove_resource::<Menu>();
+    commands.remove_resource::<MenuCursor>();
+}
+
+fn record_progress(
+    level_selection: Res<LevelSelection>,
+    mut progress: ResMut<GameProgress>,
+) {
+    if let LevelSelection::Index(index) = *level_selection {
+        progress.last_level = Some(index);
+    }
+}
+
+/// Solving a level's puzzle opens up the level after it
+fn unlock_next_level(
+    mut puzzle_solved: EventReader<PuzzleSolved>,
+    level_selection: Res<LevelSelection>,
+    mut progress: ResMut<GameProgress>,
+) {
+    for _ in puzzle_solved.iter() {
+        if let LevelSelection::Index(index) = *level_selection {
+            progress.unlocked_level = progress.unlocked_level.max(index + 1);
+        }
+    }
+}
+
+fn level_listings(
+    ldtk_assets: &Assets<LdtkAsset>,
+    level_asset: &LevelAsset,
+    broken_levels: &BrokenLevels,
+) -> Vec<LevelListing> {
+    ldtk_assets
+        .get(&level_asset.scene)
+        .map(|ldtk_asset| {
+            ldtk_asset
+                .project
+                .levels
+                .iter()
+                .map(|level| LevelListing {
+                    name: level.identifier.replace('_', " "),
+                    problem: broken_levels
+                        .0
+                        .get(&level.iid)
+                        .map(|error| error.to_string()),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn menu_page(
+    page: MenuPage,
+    progress: &GameProgress,
+    levels: &[LevelListing],
+    settings: &Settings,
+) -> Menu {
+    let option = |key: &str, choice| MenuOption {
+        label: MenuLabel::Key(Localised::new(key)),
+        choice,
+        enabled: true,
+    };
+    let playable = |index: usize| {
+        levels
+            .get(index)
+            .map_or(true, |level| level.problem.is_none())
+    };
+
+    let options = match page {
+        MenuPage::Main => {
+            let mut options = vec![
+                MenuOption {
+                    enabled: playable(0),
+                    ..option("menu.start", MenuChoice::Start)
+                },
+                MenuOption {
+                    enabled: progress.last_level.map_or(false, playable),
+                    ..option("menu.continue", MenuChoice::Continue)
+                },
+                option("menu.level_select", MenuChoice::LevelSelect),
+                option("menu.settings", MenuChoice::Settings),
+                option("menu.credits", MenuChoice::Credits),
+            ];
+            // There is nothing to quit to in a browser tab
+            if cfg!(not(target_arch = "wasm32")) {
+                options.push(option("menu.quit", MenuChoice::Quit));
+            }
+            options
+        }
+        MenuPage::LevelSelect => levels
+            .iter()
+            .enumerate()
+            .map(|(index, level)| MenuOption {
+                label: MenuLabel::Plain(level.name.clone()),
+                choice: MenuChoice::PlayLevel(index),
+                enabled: index <= progress.unlocked_level && playable(index),
+            })
+            .chain([option("menu.back", MenuChoice::Back)])
+            .collect(),
+        MenuPage::Settings => {
+            let labelled = |key: &str, value: String, choice| MenuOption {
+                label: MenuLabel::Key(Localised::with_args(key, vec![value])),
+                ..option(key, choice)
+            };
+            let volume = |key: &str, muted_key: &str, channel| {
+                let level = settings.volumes.level(channel);
+                if level.muted {
+                    option(muted_key, MenuChoice::Volume(channel))
+                } else {
+                    labelled(key, percent(level), MenuChoice::Volume(channel))
+                }
+            };
+
+            let mut options = vec![
+                labelled(
+                    "menu.language",
+                    settings.language.name().to_string(),
+                    MenuChoice::Language,
+                ),
+                volume("menu.music_volume", "menu.music_muted", Channel::Music),
+                volume(
+                    "menu.effects_volume",
+                    "menu.effects_muted",
+                    Channel::Sfx,
+                ),
+                volume(
+                    "menu.ambience_volume",
+                    "menu.ambience_muted",
+                    Channel::Ambience,
+                ),
+                volume("menu.voice_volume", "menu.voice_muted", Channel::Voice),
+                volume("menu.ui_volume", "menu.ui_muted", Channel::Ui),
+            ];
+            // The browser decides how big the canvas is
+            if cfg!(not(target_arch = "wasm32")) {
+                let key = match settings.window {
+                    WindowSetting::Windowed => "menu.windowed",
+                    WindowSetting::Fullscreen => "menu.fullscreen",
+                };
+                options.push(option(key, MenuChoice::Window));
+            }
+            options.push(option("menu.controls", MenuChoice::Controls));
+            options.push(option("menu.back", MenuChoice::Back));
+            options
+        }
+        MenuPage::Controls => ControlAction::ALL
+            .iter()
+            .map(|action| MenuOption {
+                label: MenuLabel::Key(Localised::with_args(
+                    action.key(),
+                    vec![settings.controls.describe(*action)],
+                )),
+                ..option(action.key(), MenuChoice::Rebind(*action))
+            })
+            .chain([
+                option("controls.reset", MenuChoice::ResetControls),
+                option("menu.back", MenuChoice::Back),
+            ])
+            .collect(),
+        MenuPage::Credits => vec![option("menu.back", MenuChoice::Back)],
+    };
+
+    // Broken levels are pointed out where they would be started from
+    let notices = match page {
+        MenuPage::Main | MenuPage::LevelSelect => levels
+            .iter()
+            .filter_map(|level| {
+                level.problem.as_ref().map(|problem| {
+                    Localised::with_args(
+                        "menu.broken_level",
+                        vec![level.name.clone(), problem.clone()],
+                    )
+                })
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    Menu {
+        page,
+        notices,
+        options,
+    }
+}
+
+fn percent(level: &ChannelLevel) -> String {
+    format!("{}%", (level.output() * 100.).round())
+}
+
+/// Volumes go up in steps of a fifth. The step after full mutes the channel,
+/// and the one after that unmutes it at the quietest volume.
+fn next_level(level: &ChannelLevel) -> ChannelLevel {
+    let step = (level.volume.clamp(0., 1.) * 5.).round() as u32;
+
+    if level.muted {
+        ChannelLevel {
+            volume: 0.2,
+            muted: false,
+        }
+    } else if step >= 5 {
+        ChannelLevel {
+            muted: true,
+            ..*level
+        }
+    } else {
+        ChannelLevel {
+            volume: (step + 1) as f32 / 5.,
+            muted: false,
+        }
+    }
+}
+
+fn spawn_menu_page(
+    mut commands: Commands,
+    menu: Res<Menu>,
+    font_assets: Res<FontAssets>,
+    items: Query<Entity, With<MenuItem>>,
+) {
+    if !menu.is_changed() {
+        return;
+    }
+
+    for entity in items.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let (heading, lines): (MenuLabel, &[&str]) = match menu.page {
+        MenuPage::Main => (MenuLabel::Plain(LAUNCHER_TITLE.to_string()), &[]),
+        MenuPage::LevelSelect => {
+            (MenuLabel::Key(Localised::new("menu.level_select")), &[])
+        }
+        MenuPage::Settings => {
+            (MenuLabel::Key(Localised::new("menu.settings")), &[])
+        }
+        MenuPage::Controls => (
+            MenuLabel::Key(Localised::new("menu.controls")),
+            &["controls.choose"],
+        ),
+        MenuPage::Credits => {
+            (MenuLabel::Key(Localised::new("menu.credits")), CREDITS)
+        }
+    };
+
+    let text_style = |font_size| TextStyle {
+        font: font_assets.baloo.clone(),
+        font_size,
+        color: Color::WHITE,
+    };
+    // The controls page has a long list to fit in
+    let option_size = match menu.page {
+        MenuPage::Controls => 28.0,
+        _ => 40.0,
+    };
+    let centred = Style {
+        align_self: AlignSelf::Center,
+        margin: UiRect::all(Val::Px(4.0)),
+        ..default()
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+            ..default()
+        })
+        .insert(MenuItem)
+        .with_children(|parent| {
+            spawn_label(
+                parent,
+                &heading,
+                Style {
+                    margin: UiRect::all(Val::Px(20.0)),
+                    ..centred.clone()
+                },
+                text_style(80.0),
+            );
+
+            for line in lines {
+                let mut text = parent.spawn_bundle(TextBundle {
+                    style: centred.clone(),
+                    text: Text::from_section("", text_style(24.0)),
+                    ..default()
+                });
+                text.insert(Localised::new(line));
+                if menu.page == MenuPage::Controls {
+                    text.insert(RebindingPrompt);
+                }
+            }
+
+            for notice in &menu.notices {
+                parent
+                    .spawn_bundle(TextBundle {
+                        style: centred.clone(),
+                        text: Text::from_section(
+                            "",
+                            TextStyle {
+                                color: Color::ORANGE_RED,
+                                ..text_style(24.0)
+                            },
+                        ),
+                        ..default()
+                    })
+                    .insert(notice.clone());
+            }
+
+            for (index, option) in menu.options.iter().enumerate() {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: centred.clone(),
+                        color: Color::NONE.into(),
+                        ..default()
+                    })
+                    .with_children(|button| {
+                        spawn_label(
+                            button,
+                            &option.label,
+                            default(),
+                            text_style(option_size),
+                        )
+                        .insert(MenuOptionText(index));
+                    });
+            }
+        });
+}
+
+/// Spawns the text for a label. Keyed labels start empty and are filled in
+/// from the string tables by the locale system.
+fn spawn_label<'w, 's, 'a>(
+    parent: &'a mut ChildBuilder<'w, 's, '_>,
+    label: &MenuLabel,
+    style: Style,
+    text_style: TextStyle,
+) -> EntityCommands<'w, 's, 'a> {
+    let value = match label {
+        MenuLabel::Key(_) => String::new(),
+        MenuLabel::Plain(value) => value.clone(),
+    };
+    let mut text = parent.spawn_bundle(TextBundle {
+        style,
+        text: Text::from_section(value, text_style),
+        ..default()
+    });
+    if let MenuLabel::Key(localised) = label {
+        text.insert(localised.clone());
+    }
+    text
+}
+
+fn navigate_menu(
+    mut commands: Commands,
+    action_query: Query<&ActionState<MenuAction>>,
+    menu: Res<Menu>,
+    mut cursor: ResMut<MenuCursor>,
+    progress: Res<GameProgress>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    level_asset: Res<LevelAsset>,
+    broken_levels: Res<BrokenLevels>,
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut exit: EventWriter<AppExit>,
+    audio_assets: Res<AudioAssets>,
+    ui_sounds: Res<AudioChannel<UiChannel>>,
+) {
+    // Keys pressed while rebinding belong to the controls page
+    if rebinding.action.is_some() || rebinding.is_changed() {
+        return;
+    }
+
+    let option_count = menu.options.len();
+
+    for action in &action_query {
+        if action.just_pressed(MenuAction::Up) {
+            cursor.0 = (cursor.0 + option_count - 1) % option_count;
+            ui_sounds.play(audio_assets.ui_move.clone());
+        } else if action.just_pressed(MenuAction::Down) {
+            cursor.0 = (cursor.0 + 1) % option_count;
+            ui_sounds.play(audio_assets.ui_move.clone());
+        } else if action.just_pressed(MenuAction::Select) {
+            ui_sounds.play(audio_assets.ui_select.clone());
+            choose_option(
+                &mut commands,
+                &menu,
+                cursor.0,
+                &progress,
+                &level_listings(&ldtk_assets, &level_asset, &broken_levels),
+                &mut settings,
+                &mut rebinding,
+                &mut exit,
+            );
+        } else if action.just_pressed(MenuAction::Back)
+            && menu.page != MenuPage::Main
+        {
+            ui_sounds.play(audio_assets.ui_move.clone());
+            go_to_page(
+                &mut commands,
+                menu.page.parent(),
+                &progress,
+                &level_listings(&ldtk_assets, &level_asset, &broken_levels),
+                &settings,
+            );
+        }
+    }
+}
+
+fn click_menu_options(
+    mut commands: Commands,
+    interactions: Query<
+        (&Interaction, &Children),
+        (Changed<Interaction>, With<Button>),
+    >,
+    option_texts: Query<&MenuOptionText>,
+    menu: Res<Menu>,
+    mut cursor: ResMut<MenuCursor>,
+    progress: Res<GameProgress>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    level_asset: Res<LevelAsset>,
+    broken_levels: Res<BrokenLevels>,
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut exit: EventWriter<AppExit>,
+    audio_assets: Res<AudioAssets>,
+    ui_sounds: Res<AudioChannel<UiChannel>>,
+) {
+    if rebinding.action.is_some() {
+        return;
+    }
+
+    for (interaction, children) in &interactions {
+        let index = match children
+            .iter()
+            .find_map(|child| option_texts.get(*child).ok())
+        {
+            Some(MenuOptionText(index)) => *index,
+            None => continue,
+        };
+
+        match interaction {
+            Interaction::Hovered => cursor.0 = index,
+            Interaction::Clicked => {
+                ui_sounds.play(audio_assets.ui_select.clone());
+                choose_option(
+                    &mut commands,
+                    &menu,
+                    index,
+                    &progress,
+                    &level_listings(&ldtk_assets, &level_asset, &broken_levels),
+                    &mut settings,
+                    &mut rebinding,
+                    &mut exit,
+                );
+            }
+            Interaction::None => (),
+        }
+    }
+}
+
+fn choose_option(
+    commands: &mut Commands,
+    menu: &Menu,
+    index: usize,
+    progress: &GameProgress,
+    levels: &[LevelListing],
+    settings: &mut ResMut<Settings>,
+    rebinding: &mut Rebinding,
+    exit: &mut EventWriter<AppExit>,
+) {
+    let option = match menu.options.get(index) {
+        Some(option) if option.enabled => option,
+        _ => return,
+    };
+
+    match option.choice {
+        MenuChoice::Start => start_level(commands, 0),
+        MenuChoice::Continue => {
+            start_level(commands, progress.last_level.unwrap_or(0))
+        }
+        MenuChoice::PlayLevel(level) => start_level(commands, level),
+        MenuChoice::LevelSelect => go_to_page(
+            commands,
+            MenuPage::LevelSelect,
+            progress,
+            levels,
+            settings,
+        ),
+        MenuChoice::Settings => {
+            go_to_page(commands, MenuPage::Settings, progress, levels, settings)
+        }
+        MenuChoice::Credits => {
+            go_to_page(commands, MenuPage::Credits, progress, levels, settings)
+        }
+        MenuChoice::Back => {
+            go_to_page(commands, menu.page.parent(), progress, levels, settings)
+        }
+        MenuChoice::Controls => {
+            go_to_page(commands, MenuPage::Controls, progress, levels, settings)
+        }
+        MenuChoice::Language => {
+            settings.language = settings.language.next();
+        }
+        MenuChoice::Volume(channel) => {
+            let level = settings.volumes.level_mut(channel);
+            *level = next_level(level);
+        }
+        MenuChoice::Window => settings.window = settings.window.toggled(),
+        MenuChoice::Rebind(action) => rebinding.start(action),
+        MenuChoice::ResetControls => settings.controls = Controls::default(),
+        MenuChoice::Quit => exit.send(AppExit),
+    }
+}
+
+fn start_level(commands: &mut Commands, level: usize) {
+    commands.insert_resource(LevelSelection::Index(level));
+    commands.insert_resource(NextState(GameState::GameIntro));
+}
+
+fn go_to_page(
+    commands: &mut Commands,
+    page: MenuPage,
+    progress: &GameProgress,
+    levels: &[LevelListing],
+    settings: &Settings,
+) {
+    commands.insert_resource(menu_page(page, progress, levels, settings));
+    commands.insert_resource(MenuCursor(0));
+}
+
+/// Rebuilds the settings pages to show a changed setting, leaving the cursor
+/// where it is
+fn refresh_settings_pages(
+    mut menu: ResMut<Menu>,
+    settings: Res<Settings>,
+    progress: Res<GameProgress>,
+) {
+    if settings.is_changed()
+        && matches!(menu.page, MenuPage::Settings | MenuPage::Controls)
+    {
+        *menu = menu_page(menu.page, &progress, &[], &settings);
+    }
+}
+
+/// Rebuilds the pages that list levels when a level is broken or fixed while
+/// the menu is open
+fn refresh_level_pages(
+    mut menu: ResMut<Menu>,
+    settings: Res<Settings>,
+    progress: Res<GameProgress>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    level_asset: Res<LevelAsset>,
+    broken_levels: Res<BrokenLevels>,
+) {
+    if broken_levels.is_changed()
+        && matches!(menu.page, MenuPage::Main | MenuPage::LevelSelect)
+    {
+        let levels = level_listings(&ldtk_assets, &level_asset, &broken_levels);
+        *menu = menu_page(menu.page, &progress, &levels, &settings);
+    }
+}
+
+fn highlight_menu_options(
+    menu: Res<Menu>,
+    cursor: Res<MenuCursor>,
+    mut option_texts: Query<(&MenuOptionText, &mut Text)>,
+) {
+    for (MenuOptionText(index), mut text) in option_texts.iter_mut() {
+        let enabled = menu
+            .options
+            .get(*index)
+            .map_or(false, |option| option.enabled);
+
+        text.sections[0].style.color = if !enabled {
+            Color::GRAY
+        } else if *index == cursor.0 {
+            Color::YELLOW
+        } else {
+            Color::WHITE
+        };
+    }
+}
@@ -0,0 +1,160 @@
+use bevy::prelude::*;
+use bevy_kira_audio::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::statemanagement::PauseState;
+
+pub struct MixerPlugin;
+
+impl Plugin for MixerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_channel::<MusicDeckA>()
+            .add_audio_channel::<MusicDeckB>()
+            .add_audio_channel::<SfxChannel>()
+            .add_audio_channel::<AmbienceChannel>()
+            .add_audio_channel::<VoiceChannel>()
+            .add_audio_channel::<UiChannel>()
+            .init_resource::<Mixer>()
+            .add_enter_system(PauseState::Paused, pause_game_sounds)
+            .add_exit_system(PauseState::Paused, resume_game_sounds)
+            .add_system(apply_mixer);
+    }
+}
+
+/// Music plays on two decks, so one track can fade out on one while the
+/// next fades in on the other
+pub struct MusicDeckA;
+pub struct MusicDeckB;
+
+/// Sounds made by things in the level
+pub struct SfxChannel;
+
+/// Background sounds of the forest
+pub struct AmbienceChannel;
+
+/// Spoken words, kept apart from the music and sound effects
+pub struct VoiceChannel;
+
+/// Menu and pause sounds, which keep playing while the game is paused
+pub struct UiChannel;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ChannelLevel {
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl ChannelLevel {
+    fn new(volume: f32) -> Self {
+        Self {
+            volume,
+            muted: false,
+        }
+    }
+
+    /// The volume to play at, taking mute into account
+    pub fn output(&self) -> f64 {
+        if self.muted {
+            0.
+        } else {
+            self.volume.clamp(0., 1.) as f64
+        }
+    }
+}
+
+/// The volume of each audio channel, applied whenever it changes. The music
+/// director applies `music` to the music decks itself, as it fades between
+/// tracks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Mixer {
+    pub music: ChannelLevel,
+    pub sfx: ChannelLevel,
+    pub ambience: ChannelLevel,
+    pub voice: ChannelLevel,
+    pub ui: ChannelLevel,
+}
+
+/// Names a channel's level in the mixer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Music,
+    Sfx,
+    Ambience,
+    Voice,
+    Ui,
+}
+
+impl Mixer {
+    pub fn level(&self, channel: Channel) -> &ChannelLevel {
+        match channel {
+            Channel::Music => &self.music,
+            Channel::Sfx => &self.sfx,
+            Channel::Ambience => &self.ambience,
+            Channel::Voice => &self.voice,
+            Channel::Ui => &self.ui,
+        }
+    }
+
+    pub fn level_mut(&mut self, channel: Channel) -> &mut ChannelLevel {
+        match channel {
+            Channel::Music => &mut self.music,
+            Channel::Sfx => &mut self.sfx,
+            Channel::Ambience => &mut self.ambience,
+            Channel::Voice => &mut self.voice,
+            Channel::Ui => &mut self.ui,
+        }
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self {
+            music: ChannelLevel::new(0.4),
+            sfx: ChannelLevel::new(0.8),
+            ambience: ChannelLevel::new(0.6),
+            voice: ChannelLevel::new(1.0),
+            ui: ChannelLevel::new(0.8),
+        }
+    }
+}
+
+fn apply_mixer(
+    mixer: Res<Mixer>,
+    sfx: Res<AudioChannel<SfxChannel>>,
+    ambience: Res<AudioChannel<AmbienceChannel>>,
+    voice: Res<AudioChannel<VoiceChannel>>,
+    ui: Res<AudioChannel<UiChannel>>,
+) {
+    if !mixer.is_changed() {
+        return;
+    }
+
+    sfx.set_volume(mixer.sfx.output());
+    ambience.set_volume(mixer.ambience.output());
+    voice.set_volume(mixer.voice.output());
+    ui.set_volume(mixer.ui.output());
+}
+
+/// Game sounds stop where they are while the game is paused. The music is
+/// ducked rather than paused, and the UI channel carries on.
+fn pause_game_sounds(
+    sfx: Res<AudioChannel<SfxChannel>>,
+    ambience: Res<AudioChannel<AmbienceChannel>>,
+    voice: Res<AudioChannel<VoiceChannel>>,
+) {
+    sfx.pause();
+    ambience.pause();
+    voice.pause();
+}
+
+fn resume_game_sounds(
+    sfx: Res<AudioChannel<SfxChannel>>,
+    ambience: Res<AudioChannel<AmbienceChannel>>,
+    voice: Res<AudioChannel<VoiceChannel>>,
+) {
+    sfx.resume();
+    ambience.resume();
+    voice.resume();
+}
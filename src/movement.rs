@@ -0,0 +1,279 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use heron::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    assets::MovementAssets,
+    kiwi::Kiwi,
+    level::{GroundSensor, WallCollider},
+};
+
+/// Keeps the kiwi's movement in step with `kiwi.movement.ron`, so its feel
+/// can be tuned while the game is running
+pub struct MovementPlugin;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut App) {
+        let config = KiwiMovementConfig::default();
+
+        app.add_asset::<KiwiMovementConfig>()
+            .init_asset_loader::<KiwiMovementConfigLoader>()
+            .insert_resource(Gravity::from(config.gravity_vector()))
+            .insert_resource(config)
+            .add_system(reload_movement_config)
+            .add_system(apply_movement_config);
+    }
+}
+
+/// How the kiwi moves. The copy in use is kept as a resource, replaced
+/// whenever the file is loaded or edited.
+#[derive(Debug, Clone, PartialEq, Deserialize, TypeUuid)]
+#[uuid = "9b3e7c1a-2f4d-4c8e-a6b5-0d1e2f3a4b5c"]
+#[serde(default)]
+pub struct KiwiMovementConfig {
+    /// How quickly walking speeds up, in pixels per second per second
+    pub acceleration: f32,
+    /// How quickly walking slows down or turns around
+    pub deceleration: f32,
+    /// Walking speed with a button or the stick pushed all the way
+    pub max_speed: f32,
+    /// The share of acceleration and deceleration the kiwi has in the air
+    pub air_control: f32,
+    pub jump: JumpSettings,
+    /// Speed up or down while holding on to something climbable
+    pub climb_speed: f32,
+    /// Friction of the level's walls and floors
+    pub friction: f32,
+    /// Downward pull on every dynamic body in the level
+    pub gravity: f32,
+    /// Size of the kiwi's collider
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct JumpSettings {
+    /// Upward speed the kiwi leaves the ground with
+    pub jump_speed: f32,
+    /// Seconds after walking off a ledge that a jump is still allowed
+    pub coyote_time: f32,
+    /// Seconds a jump pressed too early is held onto, to fire on landing
+    pub buffer_time: f32,
+    /// What's left of the upward speed when the button is let go early
+    pub release_cut: f32,
+}
+
+impl Default for KiwiMovementConfig {
+    fn default() -> Self {
+        Self {
+            acceleration: 600.,
+            deceleration: 900.,
+            max_speed: 60.,
+            air_control: 0.6,
+            jump: JumpSettings::default(),
+            climb_speed: 50.,
+            friction: 0.1,
+            gravity: 400.,
+            radius: 8.,
+        }
+    }
+}
+
+impl Default for JumpSettings {
+    fn default() -> Self {
+        Self {
+            jump_speed: 180.,
+            coyote_time: 0.1,
+            buffer_time: 0.12,
+            release_cut: 0.5,
+        }
+    }
+}
+
+impl KiwiMovementConfig {
+    pub fn gravity_vector(&self) -> Vec3 {
+        Vec3::new(0., -self.gravity, 0.)
+    }
+
+    /// Moves a walking speed towards the speed the player is asking for,
+    /// speeding up or slowing down at the configured rates
+    pub fn walk(
+        &self,
+        current: f32,
+        wanted: f32,
+        delta: f32,
+        grounded: bool,
+    ) -> f32 {
+        let wanted = wanted.clamp(-self.max_speed, self.max_speed);
+        let speeding_up = wanted != 0.
+            && (current == 0. || current.signum() == wanted.signum())
+            && wanted.abs() > current.abs();
+        let rate = if speeding_up {
+            self.acceleration
+        } else {
+            self.deceleration
+        };
+        let control = if grounded { 1. } else { self.air_control };
+        let step = rate * control.clamp(0., 1.) * delta;
+
+        current + (wanted - current).clamp(-step, step)
+    }
+}
+
+/// Takes up the config once it has loaded, and again each time the file is
+/// edited. A broken edit leaves the last good config in place, and the
+/// loader's error is logged by the asset server.
+fn reload_movement_config(
+    mut asset_events: EventReader<AssetEvent<KiwiMovementConfig>>,
+    configs: Res<Assets<KiwiMovementConfig>>,
+    movement_assets: Option<Res<MovementAssets>>,
+    mut config: ResMut<KiwiMovementConfig>,
+) {
+    let movement_assets = match movement_assets {
+        Some(movement_assets) => movement_assets,
+        None => return,
+    };
+    let reloaded = asset_events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+            *handle == movement_assets.kiwi
+        }
+        AssetEvent::Removed { .. } => false,
+    });
+    if !reloaded && !movement_assets.is_added() {
+        return;
+    }
+
+    if let Some(loaded) = configs.get(&movement_assets.kiwi) {
+        if *config != *loaded {
+            info!("Loaded kiwi movement config");
+            *config = loaded.clone();
+        }
+    }
+}
+
+/// Pushes the parts of the config that live on the physics world and bodies
+/// out to them, whenever the config changes or a kiwi appears
+fn apply_movement_config(
+    config: Res<KiwiMovementConfig>,
+    mut gravity: ResMut<Gravity>,
+    mut kiwi_query: Query<
+        (&mut CollisionShape, ChangeTrackers<Kiwi>),
+        With<Kiwi>,
+    >,
+    mut sensor_query: Query<
+        (
+            &mut CollisionShape,
+            &mut Transform,
+            &Parent,
+            ChangeTrackers<GroundSensor>,
+        ),
+        Without<Kiwi>,
+    >,
+    mut wall_query: Query<&mut PhysicMaterial, With<WallCollider>>,
+) {
+    if config.is_changed() {
+        *gravity = Gravity::from(config.gravity_vector());
+    }
+
+    for (mut shape, tracker) in kiwi_query.iter_mut() {
+        if config.is_changed() || tracker.is_added() {
+            *shape = CollisionShape::Sphere {
+                radius: config.radius,
+            };
+        }
+    }
+
+    // The kiwi's ground sensor sits under its collider and matches its width
+    for (mut shape, mut transform, parent, tracker) in sensor_query.iter_mut() {
+        if (config.is_changed() || tracker.is_added())
+            && kiwi_query.contains(parent.get())
+        {
+            *shape = CollisionShape::Cuboid {
+                half_extends: Vec3::new(config.radius / 2., 2., 0.),
+                border_radius: None,
+            };
+            transform.translation.y = -config.radius;
+        }
+    }
+
+    // New walls are given the friction as they are spawned
+    if config.is_changed() {
+        for mut material in wall_query.iter_mut() {
+            material.friction = config.friction;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct KiwiMovementConfigLoader;
+
+impl AssetLoader for KiwiMovementConfigLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let config: KiwiMovementConfig = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(config));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["movement.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Round numbers, so each step of a quarter second is exact
+    fn config() -> KiwiMovementConfig {
+        KiwiMovementConfig {
+            acceleration: 100.,
+            deceleration: 200.,
+            max_speed: 60.,
+            air_control: 0.5,
+            ..default()
+        }
+    }
+
+    fn walk_steps(start: f32, wanted: f32, grounded: bool) -> Vec<f32> {
+        let config = config();
+        let mut speed = start;
+        (0..4)
+            .map(|_| {
+                speed = config.walk(speed, wanted, 0.25, grounded);
+                speed
+            })
+            .collect()
+    }
+
+    #[test]
+    fn speeds_up_to_max_speed() {
+        assert_eq!(walk_steps(0., 100., true), [25., 50., 60., 60.]);
+    }
+
+    #[test]
+    fn slows_to_a_stop() {
+        assert_eq!(walk_steps(60., 0., true), [10., 0., 0., 0.]);
+    }
+
+    #[test]
+    fn turns_around_by_slowing_then_speeding_up() {
+        assert_eq!(walk_steps(60., -60., true), [10., -40., -60., -60.]);
+    }
+
+    #[test]
+    fn air_control_scales_both_rates() {
+        assert_eq!(walk_steps(0., 60., false), [12.5, 25., 37.5, 50.]);
+        assert_eq!(walk_steps(60., 0., false), [35., 10., 0., 0.]);
+    }
+}
@@ -1,9 +1,13 @@
+use std::collections::HashMap;
+
 use bevy::prelude::*;
-use bevy_kira_audio::{Audio, AudioControl};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_kira_audio::{prelude::*, AudioSource};
 use iyes_loopless::prelude::*;
 
 use crate::{
     assets::AudioAssets,
+    mixer::{Mixer, MusicDeckA, MusicDeckB},
     statemanagement::{GameState, PauseState},
 };
 
@@ -12,33 +16,211 @@ pub struct MusicPlugin;
 impl Plugin for MusicPlugin {
     fn build(&self, app: &mut App) {
         debug!("Setting up MusicPlugin");
-        app.add_enter_system(
-            GameState::GamePlaying,
-            start_gameplay_background_music,
-        )
-        .add_exit_system(GameState::GamePlaying, stop_gameplay_background_music)
-        .add_enter_system(PauseState::Paused, pause_background_music)
-        .add_exit_system(PauseState::Paused, unpause_background_music);
+        app.init_resource::<MusicSettings>()
+            .init_resource::<MusicDirector>()
+            .add_exit_system(GameState::Loading, build_playlists)
+            .add_system(direct_music);
     }
 }
 
-fn start_gameplay_background_music(
-    audio_assets: Res<AudioAssets>,
-    audio: Res<Audio>,
-) {
-    debug!("Playing music");
-    audio.play(audio_assets.soothing_nature.clone()).looped();
-    audio.set_volume(0.4);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MusicSettings {
+    /// Seconds to fade from one track into the next
+    pub crossfade_time: f32,
+    /// How loud the music is while the game is paused, relative to normal
+    pub paused_volume: f32,
+    /// Seconds to duck down to, or back up from, the paused volume
+    pub duck_time: f32,
+}
+
+impl Default for MusicSettings {
+    fn default() -> Self {
+        Self {
+            crossfade_time: 2.0,
+            paused_volume: 0.3,
+            duck_time: 0.4,
+        }
+    }
+}
+
+/// Which of the mixer's music decks a track is on. Tracks take turns, so
+/// one can fade in while the other fades out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Deck {
+    A,
+    B,
+}
+
+/// What the music should be doing for the current state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MusicCue {
+    Menu,
+    Level(usize),
+}
+
+/// Tracks to loop, taking the next one each time the playlist is cued
+#[derive(Debug, Clone, Default)]
+pub struct Playlist {
+    tracks: Vec<Handle<AudioSource>>,
+    next: usize,
+}
+
+impl Playlist {
+    pub fn new(tracks: Vec<Handle<AudioSource>>) -> Self {
+        Self { tracks, next: 0 }
+    }
+
+    fn next_track(&mut self) -> Option<Handle<AudioSource>> {
+        let track = self.tracks.get(self.next % self.tracks.len().max(1))?;
+        self.next += 1;
+        Some(track.clone())
+    }
+}
+
+/// The music for the menu and levels. A level without its own playlist
+/// uses `gameplay`.
+pub struct MusicPlaylists {
+    pub menu: Playlist,
+    pub gameplay: Playlist,
+    pub levels: HashMap<usize, Playlist>,
 }
 
-fn stop_gameplay_background_music(audio: Res<Audio>) {
-    audio.stop();
+impl MusicPlaylists {
+    fn playlist(&mut self, cue: MusicCue) -> &mut Playlist {
+        match cue {
+            MusicCue::Menu => &mut self.menu,
+            MusicCue::Level(level) => {
+                self.levels.get_mut(&level).unwrap_or(&mut self.gameplay)
+            }
+        }
+    }
+}
+
+struct MusicDirector {
+    cue: Option<MusicCue>,
+    /// The deck the current track is on
+    active: Deck,
+    /// Volume of the track fading in on the active deck, from 0 to 1
+    incoming: f32,
+    /// Volume of the track fading out on the other deck
+    outgoing: f32,
+    /// Volume relative to normal, lowered while paused
+    duck: f32,
+    /// Deck volumes as last sent, to avoid resending them every frame
+    applied: (f64, f64),
 }
 
-fn pause_background_music(audio: Res<Audio>) {
-    audio.pause();
+impl Default for MusicDirector {
+    fn default() -> Self {
+        Self {
+            cue: None,
+            active: Deck::A,
+            incoming: 0.,
+            outgoing: 0.,
+            duck: 1.,
+            applied: (-1., -1.),
+        }
+    }
+}
+
+fn build_playlists(mut commands: Commands, audio_assets: Res<AudioAssets>) {
+    commands.insert_resource(MusicPlaylists {
+        menu: Playlist::new(vec![audio_assets.rest_and_recover.clone()]),
+        gameplay: Playlist::new(vec![audio_assets.soothing_nature.clone()]),
+        levels: HashMap::new(),
+    });
+}
+
+/// The intro and play share a cue, so the level's music carries on from one
+/// to the other without starting again
+fn music_cue(state: GameState, level: &LevelSelection) -> Option<MusicCue> {
+    match state {
+        GameState::Loading => None,
+        GameState::MainMenu => Some(MusicCue::Menu),
+        GameState::GameIntro | GameState::GamePlaying => match level {
+            LevelSelection::Index(index) => Some(MusicCue::Level(*index)),
+            _ => Some(MusicCue::Level(0)),
+        },
+    }
 }
 
-fn unpause_background_music(audio: Res<Audio>) {
-    audio.resume();
+fn direct_music(
+    game_state: Res<CurrentState<GameState>>,
+    pause_state: Res<CurrentState<PauseState>>,
+    level_selection: Res<LevelSelection>,
+    playlists: Option<ResMut<MusicPlaylists>>,
+    settings: Res<MusicSettings>,
+    mixer: Res<Mixer>,
+    mut director: ResMut<MusicDirector>,
+    deck_a: Res<AudioChannel<MusicDeckA>>,
+    deck_b: Res<AudioChannel<MusicDeckB>>,
+    time: Res<Time>,
+) {
+    let cue = music_cue(game_state.0, &level_selection);
+
+    if cue != director.cue {
+        let mut playlists = match playlists {
+            Some(playlists) => playlists,
+            // Not built yet, so wait a frame rather than miss the cue
+            None => return,
+        };
+        director.cue = cue;
+        let track = cue.and_then(|cue| playlists.playlist(cue).next_track());
+
+        // Whatever is left on the other deck is cut, and the new track
+        // fades in on it while the current one fades out from where it is
+        director.active = match director.active {
+            Deck::A => Deck::B,
+            Deck::B => Deck::A,
+        };
+        director.outgoing = director.incoming;
+        director.incoming = 0.;
+        match director.active {
+            Deck::A => {
+                deck_a.stop();
+                if let Some(track) = track {
+                    deck_a.play(track).looped();
+                }
+            }
+            Deck::B => {
+                deck_b.stop();
+                if let Some(track) = track {
+                    deck_b.play(track).looped();
+                }
+            }
+        }
+    }
+
+    let delta = time.delta_seconds();
+    let fade_step = delta / settings.crossfade_time.max(0.01);
+    director.incoming = (director.incoming + fade_step).min(1.);
+    if director.outgoing > 0. {
+        director.outgoing = (director.outgoing - fade_step).max(0.);
+        if director.outgoing == 0. {
+            match director.active {
+                Deck::A => deck_b.stop(),
+                Deck::B => deck_a.stop(),
+            };
+        }
+    }
+
+    let duck_target = match pause_state.0 {
+        PauseState::Paused => settings.paused_volume,
+        PauseState::UnPaused => 1.,
+    };
+    let duck_step = delta / settings.duck_time.max(0.01);
+    director.duck += (duck_target - director.duck).clamp(-duck_step, duck_step);
+
+    let volume = mixer.music.output() * director.duck as f64;
+    let incoming = volume * director.incoming as f64;
+    let outgoing = volume * director.outgoing as f64;
+    let applied = match director.active {
+        Deck::A => (incoming, outgoing),
+        Deck::B => (outgoing, incoming),
+    };
+    if applied != director.applied {
+        deck_a.set_volume(applied.0);
+        deck_b.set_volume(applied.1);
+        director.applied = applied;
+    }
 }
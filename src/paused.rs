@@ -1,10 +1,16 @@
 use bevy::prelude::*;
+use bevy_kira_audio::prelude::*;
 use iyes_loopless::prelude::*;
 use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    assets::FontAssets,
+    assets::{AudioAssets, FontAssets},
+    controls::{Binding, ControlAction},
     helpers::despawn_entities_with,
+    locale::Localised,
+    mixer::UiChannel,
+    settings::Settings,
     statemanagement::{GameState, PauseState},
 };
 
@@ -14,6 +20,8 @@ impl Plugin for PausePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(InputManagerPlugin::<PauseMenuAction>::default())
             .add_enter_system(PauseState::Paused, spawn_pause_menu)
+            .add_enter_system(PauseState::Paused, play_pause_sound)
+            .add_exit_system(PauseState::Paused, play_pause_sound)
             .add_exit_system(
                 PauseState::Paused,
                 despawn_entities_with::<PauseMenuItem>,
@@ -29,14 +37,47 @@ impl Plugin for PausePlugin {
 #[derive(Default, Component)]
 struct PauseMenuItem;
 
-#[derive(Actionlike, Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum PauseMenuAction {
+#[derive(
+    Actionlike, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub enum PauseMenuAction {
     Close,
     ExitGame,
     Open,
 }
 
-fn spawn_pause_menu(mut commands: Commands, font_assets: Res<FontAssets>) {
+impl PauseMenuAction {
+    pub fn default_bindings() -> Vec<(Self, Binding)> {
+        vec![
+            (PauseMenuAction::Open, Binding::Key(KeyCode::Escape)),
+            (PauseMenuAction::Close, Binding::Key(KeyCode::Return)),
+            (PauseMenuAction::ExitGame, Binding::Key(KeyCode::Q)),
+            (
+                PauseMenuAction::Open,
+                Binding::Button(GamepadButtonType::Start),
+            ),
+            (
+                PauseMenuAction::Close,
+                Binding::Button(GamepadButtonType::East),
+            ),
+            (
+                PauseMenuAction::ExitGame,
+                Binding::Button(GamepadButtonType::Select),
+            ),
+        ]
+    }
+}
+
+/// The pause menu names the keys and buttons currently bound to continue
+/// and to exit
+fn spawn_pause_menu(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    settings: Res<Settings>,
+) {
+    let describe =
+        |action| vec![settings.controls.describe(ControlAction::Pause(action))];
+
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -68,7 +109,7 @@ fn spawn_pause_menu(mut commands: Commands, font_assets: Res<FontAssets>) {
                         ..default()
                     },
                     text: Text::from_section(
-                        "Paused".to_string(),
+                        "",
                         TextStyle {
                             font: font_assets.baloo.clone(),
                             font_size: 60.0,
@@ -77,62 +118,77 @@ fn spawn_pause_menu(mut commands: Commands, font_assets: Res<FontAssets>) {
                     ),
                     ..default()
                 })
+                .insert(Localised::new("pause.title"))
                 .insert(PauseMenuItem);
         })
         .with_children(|parent| {
-            parent.spawn_bundle(TextBundle {
-                style: Style {
-                    align_self: AlignSelf::Center,
-                    margin: UiRect {
-                        top: Val::Px(0.0),
-                        left: Val::Auto,
-                        bottom: Val::Px(0.0),
-                        right: Val::Auto,
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        align_self: AlignSelf::Center,
+                        margin: UiRect {
+                            top: Val::Px(0.0),
+                            left: Val::Auto,
+                            bottom: Val::Px(0.0),
+                            right: Val::Auto,
+                        },
+                        ..default()
                     },
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font: font_assets.baloo.clone(),
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        },
+                    ),
                     ..default()
-                },
-                text: Text::from_section(
-                    "Press Enter to continue".to_string(),
-                    TextStyle {
-                        font: font_assets.baloo.clone(),
-                        font_size: 40.0,
-                        color: Color::WHITE,
-                    },
-                ),
-                ..default()
-            });
-            parent.spawn_bundle(TextBundle {
-                style: Style {
-                    align_self: AlignSelf::Center,
-                    margin: UiRect {
-                        top: Val::Px(0.0),
-                        left: Val::Auto,
-                        bottom: Val::Px(0.0),
-                        right: Val::Auto,
+                })
+                .insert(Localised::with_args(
+                    "pause.continue",
+                    describe(PauseMenuAction::Close),
+                ));
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        align_self: AlignSelf::Center,
+                        margin: UiRect {
+                            top: Val::Px(0.0),
+                            left: Val::Auto,
+                            bottom: Val::Px(0.0),
+                            right: Val::Auto,
+                        },
+                        ..default()
                     },
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font: font_assets.baloo.clone(),
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        },
+                    ),
                     ..default()
-                },
-                text: Text::from_section(
-                    "Press Q to exit game".to_string(),
-                    TextStyle {
-                        font: font_assets.baloo.clone(),
-                        font_size: 40.0,
-                        color: Color::WHITE,
-                    },
-                ),
-                ..default()
-            });
+                })
+                .insert(Localised::with_args(
+                    "pause.exit",
+                    describe(PauseMenuAction::ExitGame),
+                ));
         })
         .insert(PauseMenuItem);
 }
 
-fn spawn_pause_menu_detector(mut commands: Commands) {
+/// Sounds on the UI channel, so it is heard while everything else is paused
+fn play_pause_sound(
+    audio_assets: Res<AudioAssets>,
+    ui_sounds: Res<AudioChannel<UiChannel>>,
+) {
+    ui_sounds.play(audio_assets.ui_select.clone());
+}
+
+fn spawn_pause_menu_detector(mut commands: Commands, settings: Res<Settings>) {
     commands.spawn_bundle(InputManagerBundle {
-        input_map: InputMap::new([
-            (KeyCode::Escape, PauseMenuAction::Open),
-            (KeyCode::Return, PauseMenuAction::Close),
-            (KeyCode::Q, PauseMenuAction::ExitGame),
-        ]),
+        input_map: settings.controls.input_map(),
         action_state: ActionState::default(),
     });
 }
@@ -0,0 +1,371 @@
+use bevy::{asset::LoadState, prelude::*};
+use bevy_kira_audio::{prelude::*, AudioSource};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    assets::{AudioAssets, FontAssets, VocabularyAssets},
+    helpers::despawn_entities_with,
+    locale::Localised,
+    mixer::VoiceChannel,
+    statemanagement::GameState,
+    vocabulary::{Vocabulary, WordDiscovered, WordEntry},
+};
+
+pub struct PronunciationPlugin;
+
+impl Plugin for PronunciationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NextClip>()
+            .add_event::<PronounceWord>()
+            .add_exit_system(
+                GameState::GamePlaying,
+                despawn_entities_with::<WordCard>,
+            )
+            .add_system(queue_pronunciations)
+            .add_system(play_pronunciations)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::GamePlaying)
+                    .with_system(show_word_card)
+                    .with_system(replay_word)
+                    .with_system(hide_missing_replays)
+                    .with_system(expire_word_card)
+                    .into(),
+            );
+    }
+}
+
+/// How long a newly discovered word stays on screen
+const WORD_CARD_SECONDS: f32 = 6.;
+
+/// Asks for a word to be spoken, if there is a recording of it
+pub struct PronounceWord {
+    pub word: String,
+}
+
+/// The clip waiting to be played once it has loaded. A newer request
+/// replaces an older one, so words are never spoken over each other.
+#[derive(Default)]
+struct NextClip(Option<Handle<AudioSource>>);
+
+/// Shows a word and its meaning after it is discovered
+#[derive(Component)]
+struct WordCard {
+    timer: Timer,
+}
+
+#[derive(Component)]
+struct ReplayButton {
+    word: String,
+    clip: Handle<AudioSource>,
+}
+
+fn queue_pronunciations(
+    mut pronounce: EventReader<PronounceWord>,
+    mut word_discovered: EventReader<WordDiscovered>,
+    vocabulary_assets: Option<Res<VocabularyAssets>>,
+    vocabularies: Res<Assets<Vocabulary>>,
+    asset_server: Res<AssetServer>,
+    audio_assets: Option<ResMut<AudioAssets>>,
+    mut next_clip: ResMut<NextClip>,
+) {
+    let words = pronounce
+        .iter()
+        .map(|event| &event.word)
+        .chain(word_discovered.iter().map(|event| &event.word))
+        .collect::<Vec<_>>();
+
+    let (vocabulary, mut audio_assets) = match vocabulary_assets
+        .and_then(|assets| vocabularies.get(&assets.words))
+        .zip(audio_assets)
+    {
+        Some(loaded) => loaded,
+        None => return,
+    };
+
+    for word in words {
+        if let Some(clip) = vocabulary
+            .get(word)
+            .and_then(|entry| audio_assets.words.load(entry, &asset_server))
+        {
+            next_clip.0 = Some(clip);
+        }
+    }
+}
+
+/// Plays the waiting clip once it has loaded. A clip that fails to load is
+/// dropped, leaving the word as text only.
+fn play_pronunciations(
+    mut next_clip: ResMut<NextClip>,
+    asset_server: Res<AssetServer>,
+    voice: Res<AudioChannel<VoiceChannel>>,
+) {
+    let clip = match &next_clip.0 {
+        Some(clip) => clip,
+        None => return,
+    };
+
+    match asset_server.get_load_state(clip) {
+        LoadState::Loaded => {
+            voice.stop();
+            voice.play(clip.clone());
+            next_clip.0 = None;
+        }
+        LoadState::Failed | LoadState::Unloaded => {
+            debug!("No recording to play, showing the word as text only");
+            next_clip.0 = None;
+        }
+        LoadState::NotLoaded | LoadState::Loading => (),
+    }
+}
+
+fn show_word_card(
+    mut commands: Commands,
+    mut word_discovered: EventReader<WordDiscovered>,
+    card_query: Query<Entity, With<WordCard>>,
+    vocabulary_assets: Res<VocabularyAssets>,
+    vocabularies: Res<Assets<Vocabulary>>,
+    asset_server: Res<AssetServer>,
+    mut audio_assets: ResMut<AudioAssets>,
+    font_assets: Res<FontAssets>,
+) {
+    let entry = match word_discovered
+        .iter()
+        .last()
+        .zip(vocabularies.get(&vocabulary_assets.words))
+        .and_then(|(event, vocabulary)| vocabulary.get(&event.word))
+    {
+        Some(entry) => entry,
+        None => return,
+    };
+
+    for card in card_query.iter() {
+        commands.entity(card).despawn_recursive();
+    }
+
+    let text_style = |font_size| TextStyle {
+        font: font_assets.baloo.clone(),
+        font_size,
+        color: Color::WHITE,
+    };
+    let clip = audio_assets.words.load(entry, &asset_server);
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(20.0),
+                    left: Val::Px(0.0),
+                    ..default()
+                },
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(WordCard {
+            timer: Timer::from_seconds(WORD_CARD_SECONDS, false),
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(10.0)),
+                        ..default()
+                    },
+                    color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                    ..default()
+                })
+                .with_children(|card| {
+                    spawn_word_card_contents(card, entry, clip, text_style)
+                });
+        });
+}
+
+fn spawn_word_card_contents(
+    card: &mut ChildBuilder,
+    entry: &WordEntry,
+    clip: Option<Handle<AudioSource>>,
+    text_style: impl Fn(f32) -> TextStyle,
+) {
+    card.spawn_bundle(TextBundle {
+        text: Text::from_section(entry.word.clone(), text_style(40.0)),
+        ..default()
+    });
+    card.spawn_bundle(TextBundle {
+        text: Text::from_section(entry.english.clone(), text_style(24.0)),
+        ..default()
+    });
+
+    // Words without a recording are shown as text only
+    if let Some(clip) = clip {
+        card.spawn_bundle(ButtonBundle {
+            style: Style {
+                margin: UiRect::all(Val::Px(4.0)),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            },
+            color: Color::rgba(1.0, 1.0, 1.0, 0.2).into(),
+            ..default()
+        })
+        .insert(ReplayButton {
+            word: entry.word.clone(),
+            clip,
+        })
+        .with_children(|button| {
+            button
+                .spawn_bundle(TextBundle {
+                    text: Text::from_section("", text_style(20.0)),
+                    ..default()
+                })
+                .insert(Localised::new("word.replay"));
+        });
+    }
+}
+
+fn replay_word(
+    interactions: Query<
+        (&Interaction, &ReplayButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut pronounce: EventWriter<PronounceWord>,
+) {
+    for (interaction, replay) in &interactions {
+        if *interaction == Interaction::Clicked {
+            pronounce.send(PronounceWord {
+                word: replay.word.clone(),
+            });
+        }
+    }
+}
+
+/// A recording that turns out to be missing takes its replay button with it
+fn hide_missing_replays(
+    mut commands: Commands,
+    replay_query: Query<(Entity, &ReplayButton)>,
+    asset_server: Res<AssetServer>,
+) {
+    for (entity, replay) in replay_query.iter() {
+        if asset_server.get_load_state(&replay.clip) == LoadState::Failed {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn expire_word_card(
+    mut commands: Commands,
+    mut card_query: Query<(Entity, &mut WordCard)>,
+    time: Res<Time>,
+) {
+    for (entity, mut card) in card_query.iter_mut() {
+        if card.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::HandleId;
+
+    use crate::vocabulary::WordCategory;
+
+    use super::*;
+
+    fn entry(word: &str, pronunciation: Option<&str>) -> WordEntry {
+        WordEntry {
+            word: word.to_string(),
+            english: "kiwi".to_string(),
+            category: WordCategory::Animal,
+            example: "He manu te kiwi.".to_string(),
+            pronunciation: pronunciation.map(str::to_string),
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_asset::<Vocabulary>()
+            .add_asset::<AudioSource>()
+            .add_event::<PronounceWord>()
+            .add_event::<WordDiscovered>()
+            .init_resource::<NextClip>()
+            .add_system(queue_pronunciations);
+
+        let vocabulary = Vocabulary {
+            words: vec![
+                entry("kiwi", Some("audio/words/kiwi.ogg")),
+                entry("manu", None),
+                entry("pepeke", Some("audio/words/pepeke.ogg")),
+            ],
+            phrases: Vec::new(),
+        };
+        let words = app
+            .world
+            .resource_mut::<Assets<Vocabulary>>()
+            .add(vocabulary);
+        app.insert_resource(VocabularyAssets { words })
+            .insert_resource(AudioAssets {
+                rest_and_recover: Handle::default(),
+                soothing_nature: Handle::default(),
+                ui_move: Handle::default(),
+                ui_select: Handle::default(),
+                words: default(),
+            });
+        app
+    }
+
+    fn next_clip_id(app: &App) -> Option<HandleId> {
+        app.world
+            .resource::<NextClip>()
+            .0
+            .as_ref()
+            .map(|clip| clip.id)
+    }
+
+    fn clip_id(path: &str) -> Option<HandleId> {
+        Some(HandleId::from(path))
+    }
+
+    #[test]
+    fn discovered_words_queue_their_recording() {
+        let mut app = app();
+        app.world.send_event(WordDiscovered {
+            word: "Kiwi".to_string(),
+        });
+        app.update();
+
+        assert_eq!(next_clip_id(&app), clip_id("audio/words/kiwi.ogg"));
+    }
+
+    #[test]
+    fn words_without_a_recording_queue_nothing() {
+        let mut app = app();
+        app.world.send_event(PronounceWord {
+            word: "manu".to_string(),
+        });
+        app.update();
+
+        assert_eq!(next_clip_id(&app), None);
+    }
+
+    #[test]
+    fn the_newest_request_replaces_older_ones() {
+        let mut app = app();
+        app.world.send_event(PronounceWord {
+            word: "kiwi".to_string(),
+        });
+        app.world.send_event(PronounceWord {
+            word: "pepeke".to_string(),
+        });
+        app.update();
+
+        assert_eq!(next_clip_id(&app), clip_id("audio/words/pepeke.ogg"));
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    assets::{FontAssets, VocabularyAssets},
+    bug::BugEaten,
+    kiwi::{Kiwi, KiwiAction},
+    level::Board,
+    statemanagement::{GameState, PauseState},
+    vocabulary::{DiscoveredWords, Vocabulary, WordDiscovered},
+};
+
+pub struct PuzzlePlugin;
+
+impl Plugin for PuzzlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Puzzle>()
+            .init_resource::<TileBag>()
+            .add_event::<PuzzleSolved>()
+            .add_system(plan_puzzle)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::GamePlaying)
+                    .run_not_in_state(PauseState::Paused)
+                    .with_system(collect_tiles)
+                    .with_system(drop_tile)
+                    .with_system(wobble_misplaced_tiles)
+                    .with_system(show_next_tile)
+                    .into(),
+            );
+    }
+}
+
+/// How close, in pixels, the kiwi must be to the middle of a slot to drop a
+/// tile into it
+const SLOT_REACH: f32 = 8.;
+
+/// Sent when every tile of a level's phrase is in its slot
+pub struct PuzzleSolved {
+    pub phrase: String,
+}
+
+/// The phrase to build on the current level's board, and how far along it is
+#[derive(Default)]
+pub struct Puzzle {
+    pub phrase: String,
+    words: Vec<String>,
+    /// World position of the board cell each word belongs in
+    slots: Vec<Vec2>,
+    placed: Vec<bool>,
+    pub solved: bool,
+}
+
+/// Word tiles the kiwi is carrying. The front tile is dropped next.
+#[derive(Default)]
+pub struct TileBag(pub VecDeque<String>);
+
+/// A tile sitting in the slot it belongs in
+#[derive(Component)]
+struct PlacedTile;
+
+/// A tile dropped in the wrong slot, wobbling for a moment before the kiwi
+/// picks it back up
+#[derive(Component)]
+struct MisplacedTile {
+    timer: Timer,
+}
+
+/// Text above the kiwi showing which tile it will drop next
+#[derive(Component)]
+struct NextTileLabel;
+
+/// Lays the current phrase out along the top row of the level's board once
+/// the level has been placed, spreading the slots evenly across it.
+fn plan_puzzle(
+    mut commands: Commands,
+    mut level_events: EventReader<LevelEvent>,
+    board_query: Query<(&GridCoords, &GlobalTransform), With<Board>>,
+    tile_query: Query<Entity, Or<(With<PlacedTile>, With<MisplacedTile>)>>,
+    level_selection: Res<LevelSelection>,
+    vocabulary_assets: Option<Res<VocabularyAssets>>,
+    vocabularies: Res<Assets<Vocabulary>>,
+    mut puzzle: ResMut<Puzzle>,
+    mut bag: ResMut<TileBag>,
+) {
+    if level_events
+        .iter()
+        .filter(|event| matches!(event, LevelEvent::Transformed(_)))
+        .count()
+        == 0
+    {
+        return;
+    }
+
+    for tile in tile_query.iter() {
+        commands.entity(tile).despawn_recursive();
+    }
+    *puzzle = Puzzle::default();
+    bag.0.clear();
+
+    let vocabulary = match vocabulary_assets
+        .and_then(|assets| vocabularies.get(&assets.words))
+    {
+        Some(vocabulary) => vocabulary,
+        None => return,
+    };
+    let level_index = match *level_selection {
+        LevelSelection::Index(index) => index,
+        _ => 0,
+    };
+    let phrase = match vocabulary.phrases.get(level_index) {
+        Some(phrase) => phrase,
+        None => return,
+    };
+
+    let top_row = match board_query.iter().map(|(coords, _)| coords.y).max() {
+        Some(top_row) => top_row,
+        None => return,
+    };
+    let mut cells: Vec<(i32, Vec2)> = board_query
+        .iter()
+        .filter(|(coords, _)| coords.y == top_row)
+        .map(|(coords, transform)| {
+            (coords.x, transform.translation().truncate())
+        })
+        .collect();
+    cells.sort_by_key(|(x, _)| *x);
+
+    let words = phrase.words();
+    if cells.len() < words.len() {
+        warn!(
+            "The board has {} cells but \"{}\" needs {}",
+            cells.len(),
+            phrase.te_reo,
+            words.len()
+        );
+        return;
+    }
+
+    let spacing = cells.len() as f32 / words.len() as f32;
+    let slots = (0..words.len())
+        .map(|word| cells[((word as f32 + 0.5) * spacing) as usize].1)
+        .collect();
+
+    *puzzle = Puzzle {
+        phrase: phrase.te_reo.clone(),
+        placed: vec![false; words.len()],
+        words,
+        slots,
+        solved: false,
+    };
+}
+
+/// Every bug eaten gives the kiwi a tile for a word it still needs
+fn collect_tiles(
+    mut bug_eaten: EventReader<BugEaten>,
+    puzzle: Res<Puzzle>,
+    mut bag: ResMut<TileBag>,
+    vocabulary_assets: Res<VocabularyAssets>,
+    vocabularies: Res<Assets<Vocabulary>>,
+    mut discovered: ResMut<DiscoveredWords>,
+    mut word_discovered: EventWriter<WordDiscovered>,
+) {
+    for _ in bug_eaten.iter() {
+        let needed = puzzle
+            .words
+            .iter()
+            .zip(&puzzle.placed)
+            .filter(|(_, placed)| !**placed)
+            .map(|(word, _)| word)
+            .find(|word| !bag.0.contains(word));
+
+        if let Some(word) = needed {
+            bag.0.push_back(word.clone());
+
+            let in_vocabulary = vocabularies
+                .get(&vocabulary_assets.words)
+                .map_or(false, |vocabulary| vocabulary.get(word).is_some());
+            if in_vocabulary {
+                discovered.discover(word, &mut word_discovered);
+            }
+        }
+    }
+}
+
+fn drop_tile(
+    mut commands: Commands,
+    kiwi_query: Query<(&ActionState<KiwiAction>, &GlobalTransform), With<Kiwi>>,
+    mut puzzle: ResMut<Puzzle>,
+    mut bag: ResMut<TileBag>,
+    font_assets: Res<FontAssets>,
+    mut puzzle_solved: EventWriter<PuzzleSolved>,
+) {
+    for (action_state, kiwi_transform) in kiwi_query.iter() {
+        if !action_state.just_pressed(KiwiAction::Drop) {
+            continue;
+        }
+
+        let kiwi_position = kiwi_transform.translation();
+        let slot = puzzle.slots.iter().position(|slot| {
+            (slot.x - kiwi_position.x).abs() <= SLOT_REACH
+                && kiwi_position.y > slot.y
+        });
+        let slot = match slot {
+            Some(slot) if !puzzle.placed[slot] => slot,
+            _ => continue,
+        };
+        let word = match bag.0.pop_front() {
+            Some(word) => word,
+            None => continue,
+        };
+
+        let correct = puzzle.words[slot] == word;
+        let mut tile = commands.spawn_bundle(Text2dBundle {
+            text: Text::from_section(
+                word.clone(),
+                TextStyle {
+                    font: font_assets.baloo.clone(),
+                    font_size: 12.0,
+                    color: if correct {
+                        Color::rgb(0.6, 1.0, 0.6)
+                    } else {
+                        Color::rgb(1.0, 0.75, 0.4)
+                    },
+                },
+            )
+            .with_alignment(TextAlignment::CENTER),
+            transform: Transform::from_translation(
+                puzzle.slots[slot].extend(10.),
+            ),
+            ..default()
+        });
+
+        if correct {
+            tile.insert(PlacedTile);
+            puzzle.placed[slot] = true;
+
+            if !puzzle.solved && puzzle.placed.iter().all(|placed| *placed) {
+                info!("Solved \"{}\"", puzzle.phrase);
+                puzzle.solved = true;
+                puzzle_solved.send(PuzzleSolved {
+                    phrase: puzzle.phrase.clone(),
+                });
+            }
+        } else {
+            tile.insert(MisplacedTile {
+                timer: Timer::from_seconds(1.0, false),
+            });
+            // Back to the end of the bag, so the next drop offers another
+            bag.0.push_back(word);
+        }
+    }
+}
+
+fn wobble_misplaced_tiles(
+    mut commands: Commands,
+    mut tile_query: Query<(
+        Entity,
+        &mut MisplacedTile,
+        &mut Transform,
+        &mut Text,
+    )>,
+    time: Res<Time>,
+) {
+    for (entity, mut tile, mut transform, mut text) in tile_query.iter_mut() {
+        tile.timer.tick(time.delta());
+
+        let wobble = (tile.timer.elapsed_secs() * 20.).sin()
+            * 0.2
+            * tile.timer.percent_left();
+        transform.rotation = Quat::from_rotation_z(wobble);
+        text.sections[0]
+            .style
+            .color
+            .set_a(tile.timer.percent_left());
+
+        if tile.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn show_next_tile(
+    mut commands: Commands,
+    kiwi_query: Query<Entity, With<Kiwi>>,
+    mut label_query: Query<(Entity, &mut Text, &Parent), With<NextTileLabel>>,
+    bag: Res<TileBag>,
+    font_assets: Res<FontAssets>,
+) {
+    let next = bag.0.front().cloned().unwrap_or_default();
+
+    for kiwi in kiwi_query.iter() {
+        match label_query
+            .iter_mut()
+            .find(|(_, _, parent)| parent.get() == kiwi)
+        {
+            Some((_, mut text, _)) => {
+                if text.sections[0].value != next {
+                    text.sections[0].value = next.clone();
+                }
+            }
+            None => {
+                commands.entity(kiwi).with_children(|kiwi| {
+                    kiwi.spawn_bundle(Text2dBundle {
+                        text: Text::from_section(
+                            next.clone(),
+                            TextStyle {
+                                font: font_assets.baloo.clone(),
+                                font_size: 10.0,
+                                color: Color::WHITE,
+                            },
+                        )
+                        .with_alignment(TextAlignment::CENTER),
+                        transform: Transform::from_xyz(0., 14., 1.),
+                        ..default()
+                    })
+                    .insert(NextTileLabel);
+                });
+            }
+        }
+    }
+}
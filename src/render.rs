@@ -1,15 +1,30 @@
-use bevy::{prelude::*, render::texture::ImageSettings};
+use bevy::{
+    prelude::*, render::texture::ImageSettings, transform::TransformSystem,
+};
 use iyes_loopless::prelude::AppLooplessStateExt;
 use leafwing_input_manager::Actionlike;
 
-use crate::{assets::BackgroundLayerAssets, statemanagement::GameState};
+use crate::{
+    assets::BackgroundLayerAssets, helpers::despawn_entities_with,
+    statemanagement::GameState,
+};
 
 pub struct RenderPlugin;
 
 impl Plugin for RenderPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(ImageSettings::default_nearest())
-            .add_enter_system(GameState::GamePlaying, spawn_background_layers);
+            .init_resource::<ParallaxSettings>()
+            .add_enter_system(GameState::GamePlaying, spawn_background_layers)
+            .add_exit_system(
+                GameState::GamePlaying,
+                despawn_entities_with::<ParallaxLayer>,
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                scroll_parallax_layers
+                    .before(TransformSystem::TransformPropagate),
+            );
     }
 }
 
@@ -21,28 +36,89 @@ pub enum CameraControlTemp {
     Right,
 }
 
+/// How much of the camera's movement each background layer follows, from the
+/// farthest layer to the nearest. A factor of 1 pins the layer to the screen,
+/// 0 pins it to the world.
+pub struct ParallaxSettings {
+    pub factors: [f32; 4],
+}
+
+impl Default for ParallaxSettings {
+    fn default() -> Self {
+        Self {
+            factors: [0.9, 0.75, 0.55, 0.35],
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct ParallaxLayer {
+    pub factor: f32,
+    pub tile_width: f32,
+}
+
+/// Spawns each background layer as a row of three copies of its image, so
+/// the layer can be shifted a whole tile at a time without ever showing a
+/// gap.
 pub fn spawn_background_layers(
     mut commands: Commands,
     background_layers: Res<BackgroundLayerAssets>,
+    images: Res<Assets<Image>>,
+    settings: Res<ParallaxSettings>,
+) {
+    let textures = [
+        &background_layers.background_layer_1,
+        &background_layers.background_layer_2,
+        &background_layers.background_layer_3,
+        &background_layers.background_layer_4,
+    ];
+
+    for (depth, (texture, factor)) in
+        textures.into_iter().zip(settings.factors).enumerate()
+    {
+        let tile_width = images
+            .get(texture)
+            .expect("Background layers should be loaded by this point")
+            .size()
+            .x;
+
+        commands
+            .spawn_bundle(SpatialBundle {
+                transform: Transform::from_xyz(0.0, 0.0, depth as f32),
+                ..default()
+            })
+            .insert(ParallaxLayer { factor, tile_width })
+            .with_children(|layer| {
+                for tile in -1..=1 {
+                    layer.spawn_bundle(SpriteBundle {
+                        transform: Transform::from_xyz(
+                            tile as f32 * tile_width,
+                            0.0,
+                            0.0,
+                        ),
+                        texture: texture.clone(),
+                        ..default()
+                    });
+                }
+            });
+    }
+}
+
+fn scroll_parallax_layers(
+    camera_query: Query<&Transform, (With<Camera2d>, Without<ParallaxLayer>)>,
+    mut layer_query: Query<(&mut Transform, &ParallaxLayer)>,
 ) {
-    commands.spawn_bundle(SpriteBundle {
-        transform: Transform::from_xyz(0.0, 0.0, 0.0),
-        texture: background_layers.background_layer_1.clone(),
-        ..default()
-    });
-    commands.spawn_bundle(SpriteBundle {
-        transform: Transform::from_xyz(0.0, 0.0, 1.0),
-        texture: background_layers.background_layer_2.clone(),
-        ..default()
-    });
-    commands.spawn_bundle(SpriteBundle {
-        transform: Transform::from_xyz(0.0, 0.0, 2.0),
-        texture: background_layers.background_layer_3.clone(),
-        ..default()
-    });
-    commands.spawn_bundle(SpriteBundle {
-        transform: Transform::from_xyz(0.0, 0.0, 3.0),
-        texture: background_layers.background_layer_4.clone(),
-        ..default()
-    });
+    let camera = match camera_query.get_single() {
+        Ok(camera_transform) => camera_transform.translation,
+        Err(_) => return,
+    };
+
+    for (mut transform, layer) in layer_query.iter_mut() {
+        let x = camera.x * layer.factor;
+        // Jump whole tiles to keep the middle copy under the camera
+        let tiles = ((camera.x - x) / layer.tile_width).round();
+
+        transform.translation.x = x + tiles * layer.tile_width;
+        transform.translation.y = camera.y * layer.factor;
+    }
 }
@@ -0,0 +1,287 @@
+use bevy::{prelude::*, window::WindowMode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controls::Controls,
+    locale::{Language, Locale},
+    mixer::Mixer,
+};
+
+/// Loads the player's settings as the app is built, so they are in place
+/// from the first frame, and saves them whenever they change.
+///
+/// Settings are kept in the platform's store unless a `SettingsStorage` has
+/// already been inserted, which lets tests use a `MemoryStore` instead.
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<SettingsStorage>() {
+            app.insert_resource(SettingsStorage::platform());
+        }
+        let settings = app.world.resource::<SettingsStorage>().load();
+
+        app.insert_resource(settings)
+            .add_system(apply_settings)
+            .add_system(save_settings);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+const SETTINGS_KEY: &str = "tane-game-settings";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowSetting {
+    Windowed,
+    Fullscreen,
+}
+
+impl WindowSetting {
+    pub fn toggled(self) -> Self {
+        match self {
+            WindowSetting::Windowed => WindowSetting::Fullscreen,
+            WindowSetting::Fullscreen => WindowSetting::Windowed,
+        }
+    }
+}
+
+/// Everything the player can change that should outlast the session. Fields
+/// missing from a saved file, such as ones added since it was written, take
+/// their defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub language: Language,
+    pub volumes: Mixer,
+    pub window: WindowSetting,
+    pub controls: Controls,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            language: Locale::default().language,
+            volumes: Mixer::default(),
+            window: WindowSetting::Windowed,
+            controls: Controls::default(),
+        }
+    }
+}
+
+/// Somewhere to keep the serialised settings between runs
+pub trait SettingsStore: Send + Sync {
+    fn read(&self) -> Option<String>;
+    fn write(&self, contents: &str) -> Result<(), String>;
+}
+
+pub struct SettingsStorage(pub Box<dyn SettingsStore>);
+
+impl SettingsStorage {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn platform() -> Self {
+        Self(Box::new(FileStore::in_config_dir()))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn platform() -> Self {
+        Self(Box::new(LocalStorageStore))
+    }
+
+    /// The saved settings, or the defaults if there are none or they can't
+    /// be read
+    pub fn load(&self) -> Settings {
+        let contents = match self.0.read() {
+            Some(contents) => contents,
+            None => return Settings::default(),
+        };
+
+        ron::from_str(&contents).unwrap_or_else(|error| {
+            warn!("Ignoring unreadable settings: {}", error);
+            Settings::default()
+        })
+    }
+
+    pub fn save(&self, settings: &Settings) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(
+            settings,
+            ron::ser::PrettyConfig::default(),
+        )
+        .map_err(|error| error.to_string())?;
+        self.0.write(&contents)
+    }
+}
+
+/// Keeps settings in memory only, for tests
+#[derive(Default)]
+pub struct MemoryStore(std::sync::Mutex<Option<String>>);
+
+impl SettingsStore for MemoryStore {
+    fn read(&self) -> Option<String> {
+        self.0.lock().ok()?.clone()
+    }
+
+    fn write(&self, contents: &str) -> Result<(), String> {
+        let mut stored = self.0.lock().map_err(|error| error.to_string())?;
+        *stored = Some(contents.to_string());
+        Ok(())
+    }
+}
+
+/// Keeps settings in a file in the platform's config directory
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileStore {
+    path: Option<std::path::PathBuf>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStore {
+    fn in_config_dir() -> Self {
+        Self {
+            path: config_dir()
+                .map(|dir| dir.join("tane-game").join("settings.ron")),
+        }
+    }
+}
+
+/// Where the platform expects per-user config: `%APPDATA%` on Windows,
+/// `~/Library/Application Support` on macOS and `$XDG_CONFIG_HOME` or
+/// `~/.config` elsewhere
+#[cfg(not(target_arch = "wasm32"))]
+fn config_dir() -> Option<std::path::PathBuf> {
+    use std::{env, path::PathBuf};
+
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".config")))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SettingsStore for FileStore {
+    fn read(&self) -> Option<String> {
+        std::fs::read_to_string(self.path.as_ref()?).ok()
+    }
+
+    fn write(&self, contents: &str) -> Result<(), String> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or("there is no config directory to save to")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+        }
+        std::fs::write(path, contents).map_err(|error| error.to_string())
+    }
+}
+
+/// Keeps settings in the browser's localStorage
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStorageStore;
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorageStore {
+    fn storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl SettingsStore for LocalStorageStore {
+    fn read(&self) -> Option<String> {
+        Self::storage()?.get_item(SETTINGS_KEY).ok()?
+    }
+
+    fn write(&self, contents: &str) -> Result<(), String> {
+        Self::storage()
+            .ok_or("localStorage is not available")?
+            .set_item(SETTINGS_KEY, contents)
+            .map_err(|error| format!("{:?}", error))
+    }
+}
+
+/// Passes the settings on to the parts of the game they control
+fn apply_settings(
+    settings: Res<Settings>,
+    mut locale: ResMut<Locale>,
+    mut mixer: ResMut<Mixer>,
+    mut windows: ResMut<Windows>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    if locale.language != settings.language {
+        locale.language = settings.language;
+    }
+    if *mixer != settings.volumes {
+        *mixer = settings.volumes.clone();
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        let mode = match settings.window {
+            WindowSetting::Windowed => WindowMode::Windowed,
+            WindowSetting::Fullscreen => WindowMode::BorderlessFullscreen,
+        };
+        if window.mode() != mode {
+            window.set_mode(mode);
+        }
+    }
+}
+
+fn save_settings(settings: Res<Settings>, storage: Res<SettingsStorage>) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+
+    if let Err(error) = storage.save(&settings) {
+        warn!("Couldn't save settings: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn app_with_store(store: MemoryStore) -> App {
+        let mut app = App::new();
+        app.insert_resource(Locale::default())
+            .insert_resource(Mixer::default())
+            .insert_resource(Windows::default())
+            .insert_resource(SettingsStorage(Box::new(store)))
+            .add_plugin(SettingsPlugin);
+        app.update();
+        app
+    }
+
+    #[test]
+    fn changed_settings_are_saved_and_loaded_again() {
+        let mut app = app_with_store(MemoryStore::default());
+        assert_eq!(app.world.resource::<SettingsStorage>().0.read(), None);
+
+        {
+            let mut settings = app.world.resource_mut::<Settings>();
+            settings.language = Language::English;
+            settings.volumes.music.volume = 0.1;
+        }
+        app.update();
+
+        let saved = app.world.resource::<SettingsStorage>().0.read();
+        assert!(saved.is_some());
+
+        let fresh = app_with_store(MemoryStore(Mutex::new(saved)));
+        let settings = fresh.world.resource::<Settings>();
+        assert_eq!(settings.language, Language::English);
+        assert_eq!(settings.volumes.music.volume, 0.1);
+        assert_eq!(
+            fresh.world.resource::<Locale>().language,
+            Language::English
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use bevy::{input::touch::TouchInput, prelude::*};
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::{
+    buttonlike::ButtonState, plugin::InputManagerSystem, prelude::*,
+};
+
+use crate::{
+    assets::FontAssets,
+    helpers::despawn_entities_with,
+    kiwi::{Kiwi, KiwiAction},
+    locale::Localised,
+    paused::PauseMenuAction,
+    statemanagement::{GameState, PauseState},
+};
+
+/// On-screen buttons for playing on phones and tablets. They stay hidden
+/// until the player first touches the screen, and are laid out again
+/// whenever the screen turns between portrait and landscape.
+pub struct TouchControlsPlugin;
+
+impl Plugin for TouchControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TouchControls>()
+            .add_exit_system(GameState::GamePlaying, hide_touch_controls)
+            .add_exit_system(
+                GameState::GamePlaying,
+                despawn_entities_with::<TouchOverlay>,
+            )
+            .add_system(detect_touch)
+            .add_system(
+                layout_touch_controls.run_in_state(GameState::GamePlaying),
+            )
+            // Touches are added to the actions after their own bindings have
+            // been read, so they aren't overwritten until next frame
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                press_touch_buttons.after(InputManagerSystem::Update),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TouchLayout {
+    /// Buttons sized to the height, with the walking buttons side by side at
+    /// the bottom left, the rest in a square at the bottom right and pause in
+    /// the top right corner
+    Landscape,
+    /// The same arrangement sized to the width, so the walking buttons and
+    /// the square still fit side by side
+    Portrait,
+}
+
+#[derive(Default)]
+struct TouchControls {
+    /// Set for good once the screen has been touched
+    detected: bool,
+    /// The layout on screen, if the buttons are showing
+    shown: Option<TouchLayout>,
+    /// The buttons that were being touched last frame
+    held: Vec<TouchButton>,
+}
+
+#[derive(Component)]
+struct TouchOverlay;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+enum TouchButton {
+    /// Holds down a kiwi action for as long as it is touched
+    Kiwi(KiwiAction),
+    /// Opens the pause menu, or closes it if it's open
+    Pause,
+}
+
+const BUTTON_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.15);
+const HELD_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.4);
+
+fn detect_touch(
+    mut touch_events: EventReader<TouchInput>,
+    mut touch_controls: ResMut<TouchControls>,
+) {
+    if !touch_controls.detected && touch_events.iter().next().is_some() {
+        info!("Touch input detected, showing touch controls");
+        touch_controls.detected = true;
+    }
+}
+
+fn hide_touch_controls(mut touch_controls: ResMut<TouchControls>) {
+    touch_controls.shown = None;
+}
+
+fn layout_touch_controls(
+    mut commands: Commands,
+    mut touch_controls: ResMut<TouchControls>,
+    overlay_query: Query<Entity, With<TouchOverlay>>,
+    windows: Res<Windows>,
+    font_assets: Res<FontAssets>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let layout = if !touch_controls.detected {
+        None
+    } else if window.height() > window.width() {
+        Some(TouchLayout::Portrait)
+    } else {
+        Some(TouchLayout::Landscape)
+    };
+    if layout == touch_controls.shown {
+        return;
+    }
+
+    for overlay in overlay_query.iter() {
+        commands.entity(overlay).despawn_recursive();
+    }
+    touch_controls.shown = layout;
+    let layout = match layout {
+        Some(layout) => layout,
+        None => return,
+    };
+
+    let size = match layout {
+        TouchLayout::Landscape => window.height() * 0.18,
+        TouchLayout::Portrait => window.width() * 0.2,
+    };
+    let margin = size * 0.15;
+    let at = |left: Option<f32>, right: Option<f32>, bottom: f32| UiRect {
+        left: left.map_or(Val::Auto, Val::Px),
+        right: right.map_or(Val::Auto, Val::Px),
+        bottom: Val::Px(bottom),
+        ..default()
+    };
+    let near = margin;
+    let far = margin * 2. + size;
+    let kiwi = |action| (TouchButton::Kiwi(action), size);
+    let buttons = [
+        (kiwi(KiwiAction::Left), at(Some(near), None, near), "<"),
+        (kiwi(KiwiAction::Right), at(Some(far), None, near), ">"),
+        (kiwi(KiwiAction::Up), at(None, Some(far), far), "^"),
+        (kiwi(KiwiAction::Down), at(None, Some(far), near), "v"),
+        (kiwi(KiwiAction::Drop), at(None, Some(near), far), ""),
+        (kiwi(KiwiAction::Peck), at(None, Some(near), near), ""),
+        (
+            (TouchButton::Pause, size * 0.6),
+            UiRect {
+                right: Val::Px(near),
+                top: Val::Px(near),
+                ..default()
+            },
+            "II",
+        ),
+    ];
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(TouchOverlay)
+        .with_children(|parent| {
+            for ((touch_button, size), position, symbol) in buttons {
+                let mut button = parent.spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(size), Val::Px(size)),
+                        position_type: PositionType::Absolute,
+                        position,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    color: BUTTON_COLOR.into(),
+                    ..default()
+                });
+                button.insert(touch_button).with_children(|button| {
+                    let mut text = button.spawn_bundle(TextBundle {
+                        text: Text::from_section(
+                            symbol,
+                            TextStyle {
+                                font: font_assets.baloo.clone(),
+                                font_size: size * 0.4,
+                                color: Color::WHITE,
+                            },
+                        ),
+                        ..default()
+                    });
+                    match touch_button {
+                        TouchButton::Kiwi(KiwiAction::Peck) => {
+                            text.insert(Localised::new("touch.peck"));
+                        }
+                        TouchButton::Kiwi(KiwiAction::Drop) => {
+                            text.insert(Localised::new("touch.drop"));
+                        }
+                        _ => (),
+                    }
+                });
+            }
+        });
+}
+
+/// Holds down the action of every button with a finger on it. Each touch is
+/// checked separately, so the kiwi can walk and peck at the same time. The
+/// pause button only acts when it is first touched, so holding it doesn't
+/// flick the menu open and shut.
+fn press_touch_buttons(
+    touches: Res<Touches>,
+    mut touch_controls: ResMut<TouchControls>,
+    pause_state: Res<CurrentState<PauseState>>,
+    mut button_query: Query<(
+        &TouchButton,
+        &Node,
+        &GlobalTransform,
+        &mut UiColor,
+    )>,
+    mut kiwi_query: Query<&mut ActionState<KiwiAction>, With<Kiwi>>,
+    mut pause_query: Query<&mut ActionState<PauseMenuAction>>,
+) {
+    let mut held_buttons = Vec::new();
+
+    for (button, node, transform, mut color) in button_query.iter_mut() {
+        let centre = transform.translation().truncate();
+        let held = touches.iter().any(|touch| {
+            let offset = (touch.position() - centre).abs();
+            offset.x <= node.size.x / 2. && offset.y <= node.size.y / 2.
+        });
+
+        let button_color = if held { HELD_COLOR } else { BUTTON_COLOR };
+        if color.0 != button_color {
+            color.0 = button_color;
+        }
+        if !held {
+            continue;
+        }
+
+        held_buttons.push(*button);
+        let newly_held = !touch_controls.held.contains(button);
+        match *button {
+            TouchButton::Kiwi(action) => {
+                for mut action_state in kiwi_query.iter_mut() {
+                    hold(&mut action_state, action, newly_held);
+                }
+            }
+            TouchButton::Pause if newly_held => {
+                let action = match pause_state.0 {
+                    PauseState::Paused => PauseMenuAction::Close,
+                    PauseState::UnPaused => PauseMenuAction::Open,
+                };
+                for mut action_state in pause_query.iter_mut() {
+                    action_state.press(action);
+                }
+            }
+            TouchButton::Pause => (),
+        }
+    }
+
+    touch_controls.held = held_buttons;
+}
+
+/// The input manager releases every action its own bindings aren't holding
+/// each frame. Pressing a touched action again would make it just pressed
+/// every frame, so one that was already touched is put back to held instead.
+fn hold(
+    action_state: &mut ActionState<KiwiAction>,
+    action: KiwiAction,
+    newly_held: bool,
+) {
+    if newly_held {
+        action_state.press(action);
+    } else if !action_state.pressed(action) {
+        let mut action_data = action_state.action_data(action);
+        action_data.state = ButtonState::Pressed;
+        action_state.set_action_data(action, action_data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_held_button_is_only_just_pressed_when_first_touched() {
+        let mut action_state = ActionState::<KiwiAction>::default();
+
+        hold(&mut action_state, KiwiAction::Drop, true);
+        assert!(action_state.just_pressed(KiwiAction::Drop));
+
+        // What the input manager does next frame, with no key held
+        action_state.release(KiwiAction::Drop);
+        hold(&mut action_state, KiwiAction::Drop, false);
+        assert!(action_state.pressed(KiwiAction::Drop));
+        assert!(!action_state.just_pressed(KiwiAction::Drop));
+    }
+}
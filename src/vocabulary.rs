@@ -0,0 +1,387 @@
+use std::collections::HashSet;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+pub struct VocabularyPlugin;
+
+impl Plugin for VocabularyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Vocabulary>()
+            .init_asset_loader::<VocabularyLoader>()
+            .add_event::<WordDiscovered>()
+            .init_resource::<DiscoveredWords>()
+            .add_system(report_vocabulary_reloads);
+    }
+}
+
+/// Sent the first time the player uncovers a te reo word
+pub struct WordDiscovered {
+    pub word: String,
+}
+
+/// Words the player has uncovered so far
+#[derive(Default)]
+pub struct DiscoveredWords(pub HashSet<String>);
+
+impl DiscoveredWords {
+    /// Records a word, announcing it only the first time it is seen
+    pub fn discover(
+        &mut self,
+        word: &str,
+        word_discovered: &mut EventWriter<WordDiscovered>,
+    ) {
+        if self.0.insert(word.to_lowercase()) {
+            word_discovered.send(WordDiscovered {
+                word: word.to_string(),
+            });
+        }
+    }
+}
+
+/// Every te reo word the game can teach, loaded from a `.vocab.ron` file
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "6c0f6b3e-4f0e-4b43-9a4e-5d0b8d6c2f17"]
+pub struct Vocabulary {
+    pub words: Vec<WordEntry>,
+    /// Phrases for the word-tile puzzles, one per level
+    #[serde(default)]
+    pub phrases: Vec<Phrase>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WordEntry {
+    /// The word in te reo Māori, written with macrons
+    pub word: String,
+    /// What the word means in English
+    pub english: String,
+    pub category: WordCategory,
+    /// A short te reo sentence using the word
+    pub example: String,
+    /// Path to a recording of the word, relative to the assets folder
+    #[serde(default)]
+    pub pronunciation: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Phrase {
+    pub te_reo: String,
+    pub english: String,
+}
+
+impl Phrase {
+    pub fn words(&self) -> Vec<String> {
+        self.te_reo
+            .split_whitespace()
+            .map(|word| word.trim_matches(|c: char| c.is_ascii_punctuation()))
+            .filter(|word| !word.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum WordCategory {
+    Animal,
+    Plant,
+    Nature,
+    Body,
+    Food,
+    Greeting,
+    Number,
+    Story,
+}
+
+impl Vocabulary {
+    pub fn get(&self, word: &str) -> Option<&WordEntry> {
+        self.words
+            .iter()
+            .find(|entry| entry.word.to_lowercase() == word.to_lowercase())
+    }
+
+    /// Checks every entry, returning the first problem found
+    pub fn validate(&self) -> Result<(), VocabularyError> {
+        let mut seen = HashSet::new();
+
+        for (index, entry) in self.words.iter().enumerate() {
+            let error = |problem| VocabularyError {
+                index,
+                word: entry.word.clone(),
+                problem,
+            };
+
+            if entry.word.trim().is_empty() {
+                return Err(error(EntryProblem::Empty("word")));
+            }
+            if entry.english.trim().is_empty() {
+                return Err(error(EntryProblem::Empty("english")));
+            }
+            if entry.example.trim().is_empty() {
+                return Err(error(EntryProblem::Empty("example")));
+            }
+            if let Some(letter) = first_foreign_letter(&entry.word) {
+                return Err(error(EntryProblem::NotTeReo(letter)));
+            }
+            if !seen.insert(entry.word.to_lowercase()) {
+                return Err(error(EntryProblem::Duplicate));
+            }
+            if let Some(path) = &entry.pronunciation {
+                if !path.ends_with(".ogg") {
+                    return Err(error(EntryProblem::NotOgg(path.clone())));
+                }
+            }
+        }
+
+        for (index, phrase) in self.phrases.iter().enumerate() {
+            let error = |problem| VocabularyError {
+                index,
+                word: phrase.te_reo.clone(),
+                problem,
+            };
+
+            if phrase.words().is_empty() {
+                return Err(error(EntryProblem::Empty("te_reo")));
+            }
+            if let Some(letter) = phrase
+                .words()
+                .iter()
+                .find_map(|word| first_foreign_letter(word))
+            {
+                return Err(error(EntryProblem::NotTeReo(letter)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Finds the first character that can't appear in a te reo word. The
+/// alphabet is a e h i k m n o p r t u w, the macron vowels, and g only as
+/// part of ng.
+fn first_foreign_letter(word: &str) -> Option<char> {
+    let mut previous = ' ';
+
+    for letter in word.chars() {
+        let lower = letter.to_lowercase().next().unwrap_or(letter);
+        let allowed = match lower {
+            'a' | 'e' | 'h' | 'i' | 'k' | 'm' | 'n' | 'o' | 'p' | 'r' | 't'
+            | 'u' | 'w' | 'ā' | 'ē' | 'ī' | 'ō' | 'ū' | ' ' | '-' => true,
+            'g' => previous == 'n',
+            _ => false,
+        };
+        if !allowed {
+            return Some(letter);
+        }
+        previous = lower;
+    }
+
+    None
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryProblem {
+    Empty(&'static str),
+    NotTeReo(char),
+    Duplicate,
+    NotOgg(String),
+}
+
+/// A malformed entry in a vocabulary file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VocabularyError {
+    pub index: usize,
+    pub word: String,
+    pub problem: EntryProblem,
+}
+
+impl std::fmt::Display for VocabularyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "entry {} ({:?}) ", self.index, self.word)?;
+        match &self.problem {
+            EntryProblem::Empty(field) => write!(f, "has an empty {}", field),
+            EntryProblem::NotTeReo(letter) => write!(
+                f,
+                "contains {:?}, which is not in the te reo alphabet",
+                letter
+            ),
+            EntryProblem::Duplicate => write!(f, "appears more than once"),
+            EntryProblem::NotOgg(path) => {
+                write!(f, "has pronunciation {}, which is not an .ogg", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VocabularyError {}
+
+#[derive(Default)]
+pub struct VocabularyLoader;
+
+impl AssetLoader for VocabularyLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let vocabulary: Vocabulary = ron::de::from_bytes(bytes)?;
+            vocabulary.validate()?;
+            load_context.set_default_asset(LoadedAsset::new(vocabulary));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vocab.ron"]
+    }
+}
+
+/// A broken edit leaves the last good vocabulary in place, and the loader's
+/// error is logged by the asset server.
+fn report_vocabulary_reloads(
+    mut asset_events: EventReader<AssetEvent<Vocabulary>>,
+    vocabularies: Res<Assets<Vocabulary>>,
+) {
+    for event in asset_events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            if let Some(vocabulary) = vocabularies.get(handle) {
+                info!("Reloaded vocabulary, {} words", vocabulary.words.len());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(word: &str) -> WordEntry {
+        WordEntry {
+            word: word.to_string(),
+            english: "kiwi".to_string(),
+            category: WordCategory::Animal,
+            example: "He manu te kiwi.".to_string(),
+            pronunciation: Some("audio/words/kiwi.ogg".to_string()),
+        }
+    }
+
+    fn problem(words: Vec<WordEntry>) -> Option<EntryProblem> {
+        Vocabulary {
+            words,
+            phrases: Vec::new(),
+        }
+        .validate()
+        .err()
+        .map(|error| error.problem)
+    }
+
+    fn phrase_error(phrases: &[&str]) -> Option<VocabularyError> {
+        Vocabulary {
+            words: vec![entry("kiwi")],
+            phrases: phrases
+                .iter()
+                .map(|te_reo| Phrase {
+                    te_reo: te_reo.to_string(),
+                    english: "Where is the kiwi?".to_string(),
+                })
+                .collect(),
+        }
+        .validate()
+        .err()
+    }
+
+    #[test]
+    fn valid_entry_passes() {
+        assert_eq!(problem(vec![entry("kiwi"), entry("ngārara")]), None);
+    }
+
+    #[test]
+    fn empty_fields_are_rejected() {
+        assert_eq!(
+            problem(vec![entry(" ")]),
+            Some(EntryProblem::Empty("word"))
+        );
+
+        let mut no_english = entry("kiwi");
+        no_english.english = String::new();
+        assert_eq!(
+            problem(vec![no_english]),
+            Some(EntryProblem::Empty("english"))
+        );
+
+        let mut no_example = entry("kiwi");
+        no_example.example = String::new();
+        assert_eq!(
+            problem(vec![no_example]),
+            Some(EntryProblem::Empty("example"))
+        );
+    }
+
+    #[test]
+    fn letters_outside_te_reo_are_rejected() {
+        assert_eq!(
+            problem(vec![entry("kiwis")]),
+            Some(EntryProblem::NotTeReo('s'))
+        );
+        assert_eq!(
+            problem(vec![entry("gā")]),
+            Some(EntryProblem::NotTeReo('g'))
+        );
+    }
+
+    #[test]
+    fn duplicate_words_are_rejected() {
+        let error = Vocabulary {
+            words: vec![entry("kiwi"), entry("Kiwi")],
+            phrases: Vec::new(),
+        }
+        .validate()
+        .unwrap_err();
+
+        assert_eq!(error.index, 1);
+        assert_eq!(error.problem, EntryProblem::Duplicate);
+    }
+
+    #[test]
+    fn pronunciation_must_be_ogg() {
+        let mut mp3 = entry("kiwi");
+        mp3.pronunciation = Some("audio/words/kiwi.mp3".to_string());
+        assert_eq!(
+            problem(vec![mp3]),
+            Some(EntryProblem::NotOgg("audio/words/kiwi.mp3".to_string()))
+        );
+    }
+    #[test]
+    fn phrase_words_leave_out_punctuation() {
+        let phrase = Phrase {
+            te_reo: "Kei hea te kiwi ?".to_string(),
+            english: "Where is the kiwi?".to_string(),
+        };
+        assert_eq!(phrase.words(), vec!["Kei", "hea", "te", "kiwi"]);
+    }
+
+    #[test]
+    fn valid_phrases_pass() {
+        assert_eq!(phrase_error(&["Kei hea te kiwi?", "Kia ora!"]), None);
+    }
+
+    #[test]
+    fn phrases_without_words_are_rejected() {
+        let error = phrase_error(&["Kia ora", " ?! "]).unwrap();
+        assert_eq!(error.index, 1);
+        assert_eq!(error.problem, EntryProblem::Empty("te_reo"));
+    }
+
+    #[test]
+    fn phrase_letters_outside_te_reo_are_rejected() {
+        assert_eq!(
+            phrase_error(&["Kei hea te kiwis?"]).map(|error| error.problem),
+            Some(EntryProblem::NotTeReo('s'))
+        );
+    }
+}